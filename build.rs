@@ -23,9 +23,9 @@ fn generate_coredump_parser(manifest_dir: &str, out_dir: &str) {
 fn generate_query_parser() {
     lalrpop::Configuration::new()
         .use_cargo_dir_conventions()
+        .emit_rerun_directives(true)
         .process_file("src/query/query.lalrpop")
         .expect("failed to generate parser");
-    println!("cargo:rerun-if-changed=src/query/query.lalrpop");
 }
 
 fn main() {
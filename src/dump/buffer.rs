@@ -0,0 +1,139 @@
+//! Loading snapshot files into memory.
+//!
+//! Firefox writes heap snapshots gzipped. Rather than making users decompress
+//! them by hand, we check for the gzip magic number and decompress into an
+//! owned buffer if we find it. Uncompressed files are simply mapped into
+//! memory.
+
+use anyhow::{Context, Error};
+use flate2::read::MultiGzDecoder;
+use memmap::Mmap;
+
+use std::fs::File;
+use std::io::{self, Read};
+use std::ops::Deref;
+use std::path::Path;
+
+/// The first two bytes of every gzip file.
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// The contents of a snapshot file, ready to be passed to
+/// `CoreDump::from_bytes`.
+pub enum SnapshotBuffer {
+    /// An uncompressed snapshot file, mapped into memory.
+    Mapped(Mmap),
+
+    /// The decompressed contents of a gzipped snapshot file.
+    Decompressed(Vec<u8>),
+}
+
+impl SnapshotBuffer {
+    /// Load the snapshot file at `path`, decompressing it if necessary.
+    pub fn open(path: &Path) -> Result<SnapshotBuffer, Error> {
+        let file = File::open(path)
+            .context(format!("Failed to open snapshot '{}':", path.display()))?;
+        let mmap = unsafe { Mmap::map(&file)? };
+        if !mmap.starts_with(&GZIP_MAGIC) {
+            return Ok(SnapshotBuffer::Mapped(mmap));
+        }
+
+        let bytes = decompress(&mmap)
+            .context(format!("Failed to decompress snapshot '{}'", path.display()))?;
+        Ok(SnapshotBuffer::Decompressed(bytes))
+    }
+}
+
+/// Return the decompressed contents of the gzip file `compressed`. A gzip file
+/// may consist of several compressed 'members', one after another, as produced
+/// by concatenating gzip files; decompress them all.
+fn decompress(compressed: &[u8]) -> io::Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    MultiGzDecoder::new(compressed).read_to_end(&mut bytes)?;
+    Ok(bytes)
+}
+
+impl Deref for SnapshotBuffer {
+    type Target = [u8];
+    fn deref(&self) -> &[u8] {
+        match self {
+            SnapshotBuffer::Mapped(mmap) => &mmap[..],
+            SnapshotBuffer::Decompressed(bytes) => &bytes[..],
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::dump::CoreDump;
+    use crate::dump::test_utils::{node, snapshot};
+    use flate2::Compression;
+    use flate2::write::GzEncoder;
+    use std::fs;
+    use std::io::Write;
+    use std::path::{Path, PathBuf};
+    use super::{decompress, SnapshotBuffer};
+
+    fn gzip(bytes: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(bytes).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    /// A file in the temporary directory, deleted when dropped.
+    struct TempFile(PathBuf);
+
+    impl TempFile {
+        fn new(name: &str, contents: &[u8]) -> TempFile {
+            let path = std::env::temp_dir().join(format!("fxsnapshot-{}-{}", std::process::id(), name));
+            fs::write(&path, contents).unwrap();
+            TempFile(path)
+        }
+    }
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    #[test]
+    fn multiple_members() {
+        let mut compressed = gzip(b"first member, ");
+        compressed.extend(gzip(b"second member"));
+        assert_eq!(decompress(&compressed).unwrap(), b"first member, second member");
+    }
+
+    #[test]
+    fn open() {
+        let bytes = snapshot(&[node(1, 8, &[(2, "child")], &[]), node(2, 16, &[], &[])]);
+        let count = |buffer: &SnapshotBuffer| {
+            CoreDump::from_bytes(Path::new("open"), buffer).unwrap().nodes().count()
+        };
+
+        // Plain files are mapped.
+        let plain = TempFile::new("plain", &bytes);
+        let buffer = SnapshotBuffer::open(&plain.0).unwrap();
+        assert!(matches!(buffer, SnapshotBuffer::Mapped(_)));
+        assert_eq!(&buffer[..], &bytes[..]);
+        assert_eq!(count(&buffer), 2);
+
+        // Gzipped files are recognized by their magic number, not their name.
+        let gzipped = TempFile::new("gzipped", &gzip(&bytes));
+        let buffer = SnapshotBuffer::open(&gzipped.0).unwrap();
+        assert!(matches!(buffer, SnapshotBuffer::Decompressed(_)));
+        assert_eq!(&buffer[..], &bytes[..]);
+
+        // A snapshot split across several gzip members is read in full.
+        let (front, back) = bytes.split_at(bytes.len() / 2);
+        let mut members = gzip(front);
+        members.extend(gzip(back));
+        let members = TempFile::new("members.gz", &members);
+        let buffer = SnapshotBuffer::open(&members.0).unwrap();
+        assert_eq!(&buffer[..], &bytes[..]);
+        assert_eq!(count(&buffer), 2);
+
+        let missing = std::env::temp_dir().join(format!("fxsnapshot-{}-missing", std::process::id()));
+        let error = SnapshotBuffer::open(&missing).err().expect("opening a missing file should fail");
+        assert!(error.to_string().contains("Failed to open snapshot"));
+    }
+}
@@ -0,0 +1,104 @@
+//! Checking a `CoreDump` for structural problems.
+//!
+//! Loading a snapshot only checks that each node is well-formed on its own.
+//! Queries like `paths` assume more than that: that every edge's referent is
+//! present, and so on. This module walks the whole dump looking for places
+//! where such assumptions fail.
+
+use super::{CoreDump, NodeId};
+
+use std::collections::HashSet;
+use std::fmt;
+
+/// The number of examples of each problem to keep.
+const EXAMPLES: usize = 5;
+
+/// One kind of structural problem, with the number of places it occurs, and
+/// descriptions of the first few.
+pub struct Problem {
+    pub description: &'static str,
+    pub count: usize,
+    pub examples: Vec<String>,
+}
+
+impl Problem {
+    fn new(description: &'static str) -> Problem {
+        Problem { description, count: 0, examples: Vec::new() }
+    }
+
+    /// Record an occurrence of this problem. Only call `example` if we're still
+    /// collecting examples.
+    fn add(&mut self, example: impl FnOnce() -> String) {
+        if self.count < EXAMPLES {
+            self.examples.push(example());
+        }
+        self.count += 1;
+    }
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "{}: {} ({}", self.description, self.count, self.examples.join(", "))?;
+        if self.count > self.examples.len() {
+            write!(fmt, ", ...")?;
+        }
+        write!(fmt, ")")
+    }
+}
+
+impl<'buffer> CoreDump<'buffer> {
+    /// Check the relationships between this dump's nodes, and return each kind
+    /// of problem found. Problems within individual nodes are caught by
+    /// loading, and reported in `warnings`.
+    pub fn check(&self) -> Vec<Problem> {
+        let mut dangling = Problem::new("edges referring to nodes not in the snapshot");
+        let mut no_referent = Problem::new("edges with no referent");
+        let mut sizeless = Problem::new("nodes with no size");
+        let mut unreachable = Problem::new("nodes unreachable from the root");
+
+        for node in self.nodes() {
+            let id = node.id();
+            if node.size().is_none() {
+                sizeless.add(|| format!("{:?}", id));
+            }
+            for edge in node.edges() {
+                match edge.referent {
+                    Some(referent) if !self.has_node(referent) => {
+                        dangling.add(|| format!("{:?} -> {:?}", id, referent));
+                    }
+                    Some(_) => (),
+                    None => no_referent.add(|| format!("{:?}", id)),
+                }
+            }
+        }
+
+        let reachable = self.reachable();
+        for node in self.nodes() {
+            if !reachable.contains(&node.id()) {
+                unreachable.add(|| format!("{:?}", node.id()));
+            }
+        }
+
+        [dangling, no_referent, sizeless, unreachable]
+            .into_iter()
+            .filter(|problem| problem.count > 0)
+            .collect()
+    }
+
+    /// Return the set of nodes reachable from the root.
+    fn reachable(&self) -> HashSet<NodeId> {
+        let mut reached = HashSet::new();
+        let mut stack = vec![self.root_id];
+        reached.insert(self.root_id);
+        while let Some(id) = stack.pop() {
+            for edge in self.get_node(id).unwrap().edges() {
+                if let Some(referent) = edge.referent {
+                    if self.has_node(referent) && reached.insert(referent) {
+                        stack.push(referent);
+                    }
+                }
+            }
+        }
+        reached
+    }
+}
@@ -0,0 +1,325 @@
+//! Dominator trees and retained sizes for `CoreDump`s.
+//!
+//! A node `d` dominates a node `n` if every path from the root to `n` passes
+//! through `d`. If `d` were freed, `n` would be freed too, so the total size
+//! of the nodes a node dominates, its 'retained size', is how much memory
+//! would be reclaimed if it were freed.
+//!
+//! We use the algorithm from Cooper, Harvey, and Kennedy, "A Simple, Fast
+//! Dominance Algorithm". It is asymptotically slower than Lengauer-Tarjan, but
+//! much simpler, and fast in practice on graphs like heap snapshots.
+
+use super::{CoreDump, Size};
+
+/// The dominator tree of the nodes reachable from some root.
+///
+/// Nodes are identified by their rows in the dump's node table; `CoreDump`
+/// translates to and from node ids. Internally, nodes are identified by their
+/// index in a depth-first postorder traversal of the graph, which is what the
+/// algorithm wants.
+pub struct DominatorTree {
+    /// The rows of the reachable nodes, in depth-first postorder. The root is
+    /// last.
+    postorder: Vec<u32>,
+
+    /// Each row's index in `postorder`, or `UNREACHABLE` if the root can't
+    /// reach it.
+    index: Vec<u32>,
+
+    /// The postorder index of each node's immediate dominator. The root is its
+    /// own immediate dominator.
+    idom: Vec<u32>,
+
+    /// Where each node's children begin in `dominated`. This has one more
+    /// element than there are reachable nodes.
+    dominated_starts: Vec<u32>,
+
+    /// The postorder indices of the nodes each node immediately dominates,
+    /// grouped by dominator.
+    dominated: Vec<u32>,
+
+    /// The retained size of each node: the sum of the sizes of all the nodes it
+    /// dominates, including itself.
+    retained: Vec<Size>,
+}
+
+/// The `index` entry of rows the root can't reach.
+const UNREACHABLE: u32 = u32::MAX;
+
+impl DominatorTree {
+    /// Compute the dominator tree of the nodes reachable from `dump`'s root.
+    /// Edges whose referents are absent from the dump are ignored.
+    pub fn from_dump(dump: &CoreDump) -> DominatorTree {
+        let successors = |row: u32| {
+            dump.nodes.edges(row as usize)
+                .filter_map(|edge| dump.nodes.edge_referents[edge])
+                .filter_map(|referent| dump.rows.get(&referent).copied())
+        };
+        let size = |row: u32| dump.nodes.sizes[row as usize].unwrap_or(0);
+        DominatorTree::from_graph(dump.nodes.ids.len(), dump.rows[&dump.root_id], successors, size)
+    }
+
+    /// Compute the dominator tree of the graph reachable from `root`, whose
+    /// nodes are numbered from zero to `count - 1`. `successors(n)` yields the
+    /// referents of `n`'s edges, and `size(n)` is `n`'s own size.
+    pub fn from_graph<S, I, Z>(count: usize, root: u32, successors: S, size: Z) -> DominatorTree
+    where
+        S: Fn(u32) -> I,
+        I: Iterator<Item = u32>,
+        Z: Fn(u32) -> Size,
+    {
+        let (postorder, index) = depth_first_postorder(count, root, &successors);
+        let reachable = postorder.len() as u32;
+
+        // Each node's predecessors, as postorder indices.
+        let position = |row: u32| index[row as usize];
+        let (predecessor_starts, predecessors) = group(reachable, || {
+            postorder.iter().enumerate().flat_map(|(i, &row)| {
+                successors(row).map(move |referent| (position(referent), i as u32))
+            })
+        });
+        let predecessors_of = |i: u32| {
+            &predecessors[predecessor_starts[i as usize] as usize
+                          .. predecessor_starts[i as usize + 1] as usize]
+        };
+
+        // Iterate to a fixed point, visiting nodes in reverse postorder. We use
+        // `UNDEFINED` for nodes whose dominator we haven't yet estimated; the
+        // root is always last in the postorder, and is its own dominator.
+        const UNDEFINED: u32 = u32::MAX;
+        let root_index = reachable - 1;
+        let mut idom = vec![UNDEFINED; reachable as usize];
+        idom[root_index as usize] = root_index;
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for i in (0..root_index).rev() {
+                let mut new_idom = UNDEFINED;
+                for &pred in predecessors_of(i) {
+                    if idom[pred as usize] == UNDEFINED {
+                        continue;
+                    }
+                    new_idom = if new_idom == UNDEFINED {
+                        pred
+                    } else {
+                        intersect(&idom, pred, new_idom)
+                    };
+                }
+
+                // Since we visit nodes in reverse postorder, at least one of
+                // each node's predecessors has always been processed.
+                assert!(new_idom != UNDEFINED);
+                if idom[i as usize] != new_idom {
+                    idom[i as usize] = new_idom;
+                    changed = true;
+                }
+            }
+        }
+
+        // A node's dominator is always an ancestor in the depth-first spanning
+        // tree, and so appears later in the postorder. So visiting nodes in
+        // postorder accumulates each node's retained size before adding it to
+        // its dominator's. Sizes come from the snapshot, so saturate rather
+        // than overflow if they're absurd.
+        let mut retained: Vec<Size> = postorder.iter().map(|&row| size(row)).collect();
+        for i in 0..root_index as usize {
+            let dominator = idom[i] as usize;
+            retained[dominator] = retained[dominator].saturating_add(retained[i]);
+        }
+        let (dominated_starts, dominated) =
+            group(reachable, || (0..root_index).map(|i| (idom[i as usize], i)));
+
+        DominatorTree { postorder, index, idom, dominated_starts, dominated, retained }
+    }
+
+    /// Return the row of the immediate dominator of the node in `row`. Return
+    /// `None` if `row` is the root, or is not reachable from the root.
+    pub fn immediate_dominator(&self, row: u32) -> Option<u32> {
+        let i = self.postorder_index(row)?;
+        let dominator = self.idom[i];
+        if dominator as usize == i {
+            None
+        } else {
+            Some(self.postorder[dominator as usize])
+        }
+    }
+
+    /// Return an iterator over the rows of the nodes that the node in `row`
+    /// immediately dominates. If `row` is not reachable from the root, the
+    /// iterator is empty.
+    pub fn dominated(&self, row: u32) -> impl Iterator<Item = u32> + Clone + '_ {
+        let children = match self.postorder_index(row) {
+            Some(i) => &self.dominated[self.dominated_starts[i] as usize
+                                       .. self.dominated_starts[i + 1] as usize],
+            None => &[],
+        };
+        children.iter().map(move |&child| self.postorder[child as usize])
+    }
+
+    /// Return the retained size of the node in `row`, or `None` if `row` is not
+    /// reachable from the root.
+    pub fn retained_size(&self, row: u32) -> Option<Size> {
+        self.postorder_index(row).map(|i| self.retained[i])
+    }
+
+    fn postorder_index(&self, row: u32) -> Option<usize> {
+        match self.index.get(row as usize) {
+            Some(&i) if i != UNREACHABLE => Some(i as usize),
+            _ => None,
+        }
+    }
+}
+
+/// Return the nodes reachable from `root` in depth-first postorder, along with
+/// each node's position in that order, or `UNREACHABLE`.
+fn depth_first_postorder<S, I>(count: usize, root: u32, successors: &S) -> (Vec<u32>, Vec<u32>)
+where
+    S: Fn(u32) -> I,
+    I: Iterator<Item = u32>,
+{
+    let mut postorder = Vec::new();
+    let mut index = vec![UNREACHABLE; count];
+
+    // Nodes we have started visiting, whether or not we've finished them.
+    let mut seen = vec![false; count];
+
+    // The path from the root to the node we're visiting, along with the
+    // unexplored successors of each node along it.
+    let mut stack = vec![(root, successors(root))];
+    seen[root as usize] = true;
+    while let Some((_, unexplored)) = stack.last_mut() {
+        match unexplored.next() {
+            Some(next) => {
+                if !seen[next as usize] {
+                    seen[next as usize] = true;
+                    stack.push((next, successors(next)));
+                }
+            }
+            None => {
+                let (finished, _) = stack.pop().unwrap();
+                index[finished as usize] = postorder.len() as u32;
+                postorder.push(finished);
+            }
+        }
+    }
+
+    (postorder, index)
+}
+
+/// Given a function returning `(key, value)` pairs whose keys are less than
+/// `count`, return the values grouped by key, along with where each key's group
+/// begins. Values with the same key keep their relative order. This calls
+/// `pairs` twice, once to count and once to fill.
+fn group<F, P>(count: u32, pairs: F) -> (Vec<u32>, Vec<u32>)
+where
+    F: Fn() -> P,
+    P: Iterator<Item = (u32, u32)>,
+{
+    let mut starts = vec![0; count as usize + 1];
+    for (key, _) in pairs() {
+        starts[key as usize + 1] += 1;
+    }
+    for i in 0..count as usize {
+        starts[i + 1] += starts[i];
+    }
+
+    let mut next = starts.clone();
+    let mut values = vec![0; starts[count as usize] as usize];
+    for (key, value) in pairs() {
+        values[next[key as usize] as usize] = value;
+        next[key as usize] += 1;
+    }
+    (starts, values)
+}
+
+/// Given the postorder indices `a` and `b` of two nodes whose dominators have
+/// been estimated, return the index of their nearest common dominator.
+fn intersect(idom: &[u32], mut a: u32, mut b: u32) -> u32 {
+    while a != b {
+        while a < b {
+            a = idom[a as usize];
+        }
+        while b < a {
+            b = idom[b as usize];
+        }
+    }
+    a
+}
+
+#[cfg(test)]
+mod test {
+    use super::DominatorTree;
+
+    /// Build a dominator tree for the graph whose edges are `edges`, rooted at
+    /// `root`, where every node has size 1. Nodes are numbered below 10.
+    fn tree(root: u32, edges: &[(u32, u32)]) -> DominatorTree {
+        let successors = |node: u32| {
+            edges.iter()
+                .filter(move |&&(from, _)| from == node)
+                .map(|&(_, to)| to)
+        };
+        DominatorTree::from_graph(10, root, successors, |_| 1)
+    }
+
+    fn idom(tree: &DominatorTree, node: u32) -> Option<u32> {
+        tree.immediate_dominator(node)
+    }
+
+    fn dominated(tree: &DominatorTree, node: u32) -> Vec<u32> {
+        let mut nodes: Vec<u32> = tree.dominated(node).collect();
+        nodes.sort();
+        nodes
+    }
+
+    #[test]
+    fn diamond() {
+        // 1 -> 2 -> 4, 1 -> 3 -> 4, 4 -> 5
+        let tree = tree(1, &[(1, 2), (1, 3), (2, 4), (3, 4), (4, 5)]);
+        assert_eq!(idom(&tree, 1), None);
+        assert_eq!(idom(&tree, 2), Some(1));
+        assert_eq!(idom(&tree, 3), Some(1));
+        assert_eq!(idom(&tree, 4), Some(1));
+        assert_eq!(idom(&tree, 5), Some(4));
+        assert_eq!(dominated(&tree, 1), vec![2, 3, 4]);
+        assert_eq!(tree.retained_size(1), Some(5));
+        assert_eq!(tree.retained_size(4), Some(2));
+        assert_eq!(tree.retained_size(2), Some(1));
+    }
+
+    #[test]
+    fn cooper_harvey_kennedy_figure_4() {
+        // The irreducible graph from figure 4 of the paper. Every node's
+        // immediate dominator is the root, but it takes more than one pass to
+        // discover that.
+        let tree = tree(6, &[(6, 5), (6, 4), (5, 1), (4, 2), (4, 3),
+                             (1, 2), (2, 1), (2, 3), (3, 2)]);
+        for id in 1..=5 {
+            assert_eq!(idom(&tree, id), Some(6));
+        }
+        assert_eq!(dominated(&tree, 6), vec![1, 2, 3, 4, 5]);
+        assert_eq!(tree.retained_size(6), Some(6));
+        assert_eq!(tree.retained_size(2), Some(1));
+    }
+
+    #[test]
+    fn unreachable() {
+        let tree = tree(1, &[(1, 2), (3, 2)]);
+        assert_eq!(idom(&tree, 3), None);
+        assert_eq!(dominated(&tree, 3), Vec::<u32>::new());
+        assert_eq!(tree.retained_size(3), None);
+        assert_eq!(tree.retained_size(1), Some(2));
+    }
+
+    #[test]
+    fn huge_sizes() {
+        // 1 -> 2, 1 -> 3, where 2 and 3 are as large as sizes get.
+        let successors = |node: u32| {
+            let children: &[u32] = if node == 1 { &[2, 3] } else { &[] };
+            children.iter().copied()
+        };
+        let size = |node: u32| if node == 1 { 1 } else { u64::MAX };
+        let tree = DominatorTree::from_graph(4, 1, successors, size);
+        assert_eq!(tree.retained_size(2), Some(u64::MAX));
+        assert_eq!(tree.retained_size(1), Some(u64::MAX));
+    }
+}
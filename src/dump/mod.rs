@@ -14,10 +14,26 @@ mod generated {
 }
 use self::generated::mozilla::devtools::protobuf;
 
+// intra-crate modules
+mod buffer;
+mod check;
+mod dominators;
+mod referrers;
+mod table;
+pub mod test_utils;
+
+pub use self::buffer::SnapshotBuffer;
+use self::dominators::DominatorTree;
+use self::referrers::Referrers;
+use self::table::{NodeTable, Row, StringIndex};
+
 // std uses
+use std::cell::OnceCell;
 use std::collections::HashMap;
 use std::fmt::{self, Write};
+use std::ops::Range;
 use std::path::{Path, PathBuf};
+use std::ptr;
 
 pub struct CoreDump<'buffer> {
     /// The filename, solely for use in error messages.
@@ -26,50 +42,93 @@ pub struct CoreDump<'buffer> {
     /// The core dump's timestamp.
     pub timestamp: Option<u64>,
 
+    /// Descriptions of problems encountered while loading that didn't stop
+    /// it: duplicate node ids, and, when loaded with `from_bytes_lenient`,
+    /// the malformed nodes skipped.
+    pub warnings: Vec<String>,
+
     /// The id of the root node.
     root_id: NodeId,
 
     /// A map from deduplicated string indices to one-byte strings borrowed out
-    /// of the buffer holding the core dump.
-    one_byte_strings: Vec<OneByteString<'buffer>>,
+    /// of the buffer holding the core dump. `None` marks a malformed string,
+    /// which a lenient load skips, but which still occupies an index.
+    one_byte_strings: Vec<Option<OneByteString<'buffer>>>,
 
     /// A map from deduplicated string indices to two-byte strings borrowed out
-    /// of the buffer holding the core dump.
-    two_byte_strings: Vec<TwoByteString<'buffer>>,
+    /// of the buffer holding the core dump, with `None` marking malformed
+    /// strings.
+    two_byte_strings: Vec<Option<TwoByteString<'buffer>>>,
+
+    /// All the nodes and edges, stored column by column. `Node` values are
+    /// handles on rows of this table.
+    nodes: NodeTable,
+
+    /// A map from node id's to their rows in `nodes`. When two nodes have the
+    /// same id, the later one's row replaces the earlier one's here.
+    rows: HashMap<NodeId, u32>,
+
+    /// The rows of all the nodes in `rows`, sorted by id. We iterate over this,
+    /// rather than `rows`, so that query results don't depend on hash table
+    /// order.
+    order: Vec<u32>,
 
-    /// A map from node id's to parsed Nodes.
-    nodes: HashMap<NodeId, Node<'buffer>>,
+    /// A map from frame id's to parsed stack Frames. Nodes' allocation stacks
+    /// and frames' parents refer to entries in this table.
+    frames: HashMap<FrameId, Frame<'buffer>>,
+
+    /// The dominator tree of the nodes reachable from the root, computed the
+    /// first time someone asks for it.
+    dominator_tree: OnceCell<DominatorTree>,
+
+    /// An index of each node's incoming edges, built the first time someone
+    /// asks for it. Queries that never look at referrers never pay for it.
+    referrers: OnceCell<Referrers>,
 }
 
-/// A ubi::Node from a core dump.
-#[allow(non_snake_case)] // These names should match those found in CoreDump.proto.
-#[derive(Clone)]
-pub struct Node<'buffer> {
-    pub id: NodeId,
-    pub size: Option<Size>,
-    pub edges: Vec<Edge<'buffer>>,
-    // allocationStack
-    pub coarseType: CoarseType,
-    pub typeName: Option<TwoByteString<'buffer>>,
-    pub JSObjectClassName: Option<OneByteString<'buffer>>,
-    pub scriptFilename: Option<OneByteString<'buffer>>,
-    pub descriptiveTypeName: Option<TwoByteString<'buffer>>,
+/// A ubi::Node from a core dump. This is just a handle on a row of the dump's
+/// node table; its methods retrieve the node's fields.
+#[derive(Clone, Copy)]
+pub struct Node<'a> {
+    dump: &'a CoreDump<'a>,
+    row: u32,
 }
 
 /// An edge from one ubi::Node to another, from a core dump.
-#[derive(Clone, Eq, PartialEq)]
+#[derive(Clone, Copy, Eq, PartialEq)]
 pub struct Edge<'buffer> {
     pub referent: Option<NodeId>,
     pub name: Option<TwoByteString<'buffer>>,
 }
 
+/// An iterator over a node's edges.
+#[derive(Clone)]
+pub struct Edges<'a> {
+    dump: &'a CoreDump<'a>,
+    indices: Range<usize>,
+}
+
+/// A stack frame from a core dump, as recorded in a node's allocation stack.
+#[allow(non_snake_case)] // These names should match those found in CoreDump.proto.
+#[derive(Clone)]
+pub struct Frame<'buffer> {
+    pub id: FrameId,
+    pub source: Option<TwoByteString<'buffer>>,
+    pub line: Option<u32>,
+    pub column: Option<u32>,
+    pub functionDisplayName: Option<TwoByteString<'buffer>>,
+    pub isSystem: Option<bool>,
+    pub isSelfHosted: Option<bool>,
+    pub parent: Option<FrameId>,
+}
+
 /// A snapshot node id.
-#[derive(Clone, Copy, Eq, PartialEq, Hash)]
+#[derive(Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Hash)]
 pub struct NodeId(pub u64);
 
 /// A snapshot stack frame id.
-#[derive(Clone, Copy, Eq, PartialEq, Hash)]
-pub struct FrameId(u64);
+#[derive(Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct FrameId(pub u64);
 
 /// The size of an object mentioned in a core dump.
 ///
@@ -83,9 +142,11 @@ pub type Size = u64;
 #[derive(Copy, Clone, Eq, PartialEq)]
 pub struct OneByteString<'a>(&'a [u8]);
 
-/// A slice of untrusted UTF-16 text, possibly borrowed from a dump.
+/// A slice of untrusted UTF-16 text, possibly borrowed from a dump. This holds
+/// the little-endian bytes as they appear in the dump, since they needn't be
+/// suitably aligned for `u16`. The length is always even.
 #[derive(Copy, Clone, Eq, PartialEq)]
-pub struct TwoByteString<'a>(&'a [u16]);
+pub struct TwoByteString<'a>(&'a [u8]);
 
 #[derive(Clone, Copy, Debug)]
 pub enum CoarseType {
@@ -97,7 +158,24 @@ pub enum CoarseType {
 }
 
 impl<'buffer> CoreDump<'buffer> {
+    /// Parse the heap snapshot `bytes`, read from the file at `path`. If any
+    /// part of the snapshot is malformed, return an error giving its offset.
     pub fn from_bytes<'p>(path: &'p Path, bytes: &'buffer [u8]) -> Result<CoreDump<'buffer>, Error> {
+        CoreDump::load(path, bytes, false)
+    }
+
+    /// Like `from_bytes`, but skip malformed nodes, rather than failing. If
+    /// the snapshot is truncated, keep the nodes before the cut. Record each
+    /// problem in the dump's `warnings`. The metadata and root node must still
+    /// be intact.
+    ///
+    /// Firefox content processes that crash while writing a snapshot leave
+    /// truncated files, which are still worth exploring.
+    pub fn from_bytes_lenient<'p>(path: &'p Path, bytes: &'buffer [u8]) -> Result<CoreDump<'buffer>, Error> {
+        CoreDump::load(path, bytes, true)
+    }
+
+    fn load<'p>(path: &'p Path, bytes: &'buffer [u8], lenient: bool) -> Result<CoreDump<'buffer>, Error> {
         let mut reader = BytesReader::from_bytes(bytes);
         let metadata: protobuf::Metadata = reader
             .read_message(bytes)
@@ -110,87 +188,282 @@ impl<'buffer> CoreDump<'buffer> {
         let mut dump = CoreDump {
             path: path.to_owned(),
             timestamp: metadata.timeStamp,
+            warnings: Vec::new(),
             root_id: NodeId(0),
             one_byte_strings: Vec::new(),
             two_byte_strings: Vec::new(),
-            nodes: HashMap::new(),
+            nodes: NodeTable::new(),
+            rows: HashMap::new(),
+            order: Vec::new(),
+            frames: HashMap::new(),
+            dominator_tree: OnceCell::new(),
+            referrers: OnceCell::new(),
         };
 
-        // Scan the root node.
+        // Scan the root node. Even a lenient load can't do without this.
+        let offset = bytes.len() - reader.len();
         let root_node: protobuf::Node = reader.read_message(bytes)
-            .with_context(|| format!("{}: couldn't read root node:", path.display()))?;
+            .with_context(|| format!("{}: couldn't read root node at offset {:x}:",
+                                     path.display(), offset))?;
         dump.root_id = match root_node.id {
-            None => bail!("{}: root node has no id", path.display()),
+            None => bail!("{}: root node at offset {:x} has no id", path.display(), offset),
             Some(id) => NodeId(id),
         };
-        dump.scan_node(&root_node);
+        dump.scan_node(&root_node)
+            .with_context(|| format!("{}: bad root node at offset {:x}:", path.display(), offset))?;
 
         // Scan all remaining nodes and stack frames.
         while !reader.is_eof() {
+            let offset = bytes.len() - reader.len();
+
             // Don't format an error message unless an error actually occurs.
-            let node: protobuf::Node = reader.read_message(bytes)
-                .with_context(|| format!(
-                    "Couldn't read node from {} at offset {:x}:",
+            let node: protobuf::Node = match reader.read_message(bytes) {
+                Ok(node) => node,
+                Err(error) => {
+                    let error = Error::from(error).context(format!(
+                        "Couldn't read node from {} at offset {:x}:",
+                        path.display(),
+                        offset
+                    ));
+                    if !lenient {
+                        return Err(error);
+                    }
+                    // There's no way to find the start of the next node, so
+                    // this is as far as we can go.
+                    dump.warnings.push(format!("{:#}; ignoring the rest of the snapshot", error));
+                    break;
+                }
+            };
+
+            // If two nodes have the same id, the later one wins, but say so.
+            let duplicate = node.id.is_some_and(|id| dump.has_node(NodeId(id)));
+            if let Err(error) = dump.scan_node(&node) {
+                let error = error.context(format!(
+                    "Bad node in {} at offset {:x}:",
                     path.display(),
-                    bytes.len() - reader.len()
-                ))?;
+                    offset
+                ));
+                if !lenient {
+                    return Err(error);
+                }
+                dump.warnings.push(format!("{:#}; skipping it", error));
+            } else if duplicate {
+                dump.warnings.push(format!(
+                    "Node in {} at offset {:x} has the same id as an earlier node, {:?}; replacing it",
+                    path.display(),
+                    offset,
+                    NodeId(node.id.unwrap())
+                ));
+            }
+        }
+
+        dump.order = dump.rows.values().cloned().collect();
+        dump.order.sort_by_key(|&row| dump.nodes.ids[row as usize]);
 
-            dump.scan_node(&node);
+        // Replaced nodes leave dead rows behind, edges and all. Duplicate ids
+        // are rare, so rather than complicate loading, copy the live rows to a
+        // fresh table afterwards.
+        if dump.order.len() < dump.nodes.ids.len() {
+            dump.nodes = dump.nodes.select_rows(&dump.order)?;
+            dump.order = (0..dump.order.len() as u32).collect();
+            dump.rows = dump.order.iter().map(|&row| (dump.nodes.ids[row as usize], row)).collect();
         }
+        dump.nodes.shrink_to_fit();
+        dump.rows.shrink_to_fit();
 
         Ok(dump)
     }
 
-    pub fn get_root(&self) -> &Node<'buffer> {
-        // root_id had better be present in the table.
-        &self.nodes[&self.root_id]
+    pub fn get_root(&self) -> Node<'_> {
+        // `load` fails if it can't scan the root node, so root_id is always
+        // present in the table.
+        self.node_at(self.rows[&self.root_id])
     }
 
-    pub fn get_node(&self, id: NodeId) -> Option<&Node<'buffer>> {
-        self.nodes.get(&id)
+    pub fn get_node(&self, id: NodeId) -> Option<Node<'_>> {
+        self.rows.get(&id).map(|&row| self.node_at(row))
     }
 
-    pub fn nodes<'a>(&'a self) -> impl Iterator<Item = &Node<'buffer>> + Clone + 'a {
-        self.nodes.values()
+    /// Return an iterator over all the nodes in the dump, in order of
+    /// increasing id.
+    pub fn nodes<'a>(&'a self) -> impl Iterator<Item = Node<'a>> + Clone + 'a {
+        self.order.iter().map(move |&row| self.node_at(row))
     }
 
     pub fn has_node(&self, id: NodeId) -> bool {
-        self.nodes.contains_key(&id)
+        self.rows.contains_key(&id)
+    }
+
+    fn node_at(&self, row: u32) -> Node<'_> {
+        Node { dump: self, row }
+    }
+
+    pub fn get_frame(&self, id: FrameId) -> Option<&Frame<'buffer>> {
+        self.frames.get(&id)
+    }
+
+    /// Return true if `frame` is one of this dump's frames, not merely one with
+    /// the same id from some other dump.
+    pub fn owns_frame(&self, frame: &Frame) -> bool {
+        self.get_frame(frame.id).is_some_and(|own| ptr::eq(own, frame))
+    }
+
+    /// Return the dominator tree of this dump's nodes, computing it if this is
+    /// the first request.
+    fn dominator_tree(&self) -> &DominatorTree {
+        self.dominator_tree.get_or_init(|| DominatorTree::from_dump(self))
+    }
+
+    /// Return the immediate dominator of the node `id`. Return `None` if `id`
+    /// is the root, or is not reachable from the root.
+    pub fn immediate_dominator(&self, id: NodeId) -> Option<Node<'_>> {
+        let &row = self.rows.get(&id)?;
+        self.dominator_tree().immediate_dominator(row).map(|row| self.node_at(row))
+    }
+
+    /// Return an iterator over the nodes that the node `id` immediately
+    /// dominates. If `id` is not reachable from the root, the iterator is
+    /// empty.
+    pub fn dominated<'a>(&'a self, id: NodeId) -> impl Iterator<Item = Node<'a>> + Clone + 'a {
+        let tree = self.dominator_tree();
+        self.rows.get(&id)
+            .into_iter()
+            .flat_map(move |&row| tree.dominated(row))
+            .map(move |row| self.node_at(row))
+    }
+
+    /// Return the retained size of the node `id`, or `None` if `id` is not
+    /// reachable from the root.
+    pub fn retained_size(&self, id: NodeId) -> Option<Size> {
+        let &row = self.rows.get(&id)?;
+        self.dominator_tree().retained_size(row)
+    }
+
+    /// Return an iterator over the edges that refer to the node `id`, each
+    /// paired with the node it belongs to. Build the reverse edge index if
+    /// this is the first request.
+    pub fn referrers<'a>(&'a self, id: NodeId)
+        -> impl Iterator<Item = (Node<'a>, Edge<'a>)> + Clone + 'a
+    {
+        let index = self.referrers.get_or_init(|| Referrers::from_dump(self));
+        let incoming = match self.rows.get(&id) {
+            Some(&row) => index.get(row),
+            None => &[],
+        };
+        incoming.iter().map(move |&(origin, edge)| {
+            (self.node_at(origin), self.edge_at(edge as usize))
+        })
     }
 }
 
 // Methods for scanning the protobuf stream.
 //
-// These methods build the map from ids to buffer offsets, and build the
-// deduplicated string tables. No owning representations of nodes, edges,
-// frames, strings, etc. are built; everything borrows out of the buffer.
+// These methods build the node table, the frame table, and the deduplicated
+// string tables. Strings are never copied; they borrow out of the buffer.
 impl<'buffer> CoreDump<'buffer> {
-    fn scan_node(&mut self, proto: &protobuf::Node<'buffer>) {
-        self.intern(&proto.TypeNameOrRef);
-        for edge in &proto.edges {
-            self.intern(&edge.EdgeNameOrRef);
+    fn scan_node(&mut self, proto: &protobuf::Node<'buffer>) -> Result<(), Error> {
+        let result = self.scan_node_row(proto);
+        if result.is_err() {
+            self.nodes.discard_edges();
         }
+        result
+    }
 
-        self.scan_frame(proto.allocationStack.as_ref());
+    /// Add `proto` to the node table. If this fails, the caller must discard
+    /// whatever edges we pushed.
+    fn scan_node_row(&mut self, proto: &protobuf::Node<'buffer>) -> Result<(), Error> {
+        // Strings must be interned in the order Firefox wrote them, so that
+        // back references find the right ones. Intern every string in the node
+        // before reporting any problem with it, so that a lenient load that
+        // skips this node still numbers the strings after it correctly. For the
+        // same reason, record the node's stack frames even if we skip it: later
+        // nodes may refer to them.
+        let type_name = self.intern(&proto.TypeNameOrRef);
+        let mut edge_error = None;
+        for edge in &proto.edges {
+            let name = self.intern(&edge.EdgeNameOrRef).unwrap_or_else(|error| {
+                edge_error.get_or_insert(error);
+                StringIndex::NONE
+            });
+            self.nodes.push_edge(edge.referent.map(NodeId), name);
+        }
+        let stack = proto.allocationStack.as_ref();
+        let frames = self.intern_frame_strings(stack).and_then(|()| self.scan_frame(stack));
+        let js_object_class_name = self.intern(&proto.JSObjectClassNameOrRef);
+        let script_filename = self.intern(&proto.ScriptFilenameOrRef);
+        let descriptive_type_name = self.intern(&proto.descriptiveTypeNameOrRef);
 
-        self.intern(&proto.JSObjectClassNameOrRef);
-        self.intern(&proto.ScriptFilenameOrRef);
-        self.intern(&proto.descriptiveTypeNameOrRef);
+        let type_name = type_name?;
+        if let Some(error) = edge_error {
+            return Err(error);
+        }
+        frames?;
+        let js_object_class_name = js_object_class_name?;
+        let script_filename = script_filename?;
+        let descriptive_type_name = descriptive_type_name?;
 
-        let node = Node::from_protobuf(proto, self);
-        self.nodes.insert(node.id, node);
+        let id = match proto.id {
+            None => bail!("node has no id"),
+            Some(id) => NodeId(id),
+        };
+        let row = self.nodes.push_row(Row {
+            id,
+            size: proto.size,
+            allocation_stack: proto.allocationStack.as_ref().and_then(frame_id),
+            coarse_type: CoarseType::try_from(proto.coarseType)?,
+            type_name,
+            js_object_class_name,
+            script_filename,
+            descriptive_type_name,
+        })?;
+        self.rows.insert(id, row);
+        Ok(())
     }
 
-    fn scan_frame(&mut self, mut frame: Option<&protobuf::StackFrame<'buffer>>) {
+    /// Intern the strings of `frame` and all its ancestors, returning the first
+    /// error, if any, only after interning them all.
+    fn intern_frame_strings(&mut self, mut frame: Option<&protobuf::StackFrame<'buffer>>)
+                            -> Result<(), Error>
+    {
         use self::generated::mozilla::devtools::protobuf::mod_StackFrame::OneOfStackFrameType;
-        while let Some(protobuf::StackFrame {
-            StackFrameType: OneOfStackFrameType::data(data),
-        }) = frame
-        {
-            self.intern(&data.SourceOrRef);
-            self.intern(&data.FunctionDisplayNameOrRef);
+        let mut result = Ok(());
+        while let Some(OneOfStackFrameType::data(data)) = frame.map(|proto| &proto.StackFrameType) {
+            let source = self.intern(&data.SourceOrRef);
+            let name = self.intern(&data.FunctionDisplayNameOrRef);
+            if result.is_ok() {
+                result = source.and(name).map(drop);
+            }
             frame = data.parent.as_deref();
         }
+        result
+    }
+
+    /// Record `frame` and all its ancestors in the frame table. Their strings
+    /// must already have been interned, by `intern_frame_strings`.
+    ///
+    /// Firefox serializes each distinct frame's data only once; later
+    /// occurrences are `ref` variants giving the id of the frame serialized
+    /// earlier. Every chain of `data` frames ends either at the outermost
+    /// frame, or at a `ref` to some frame we have already recorded.
+    fn scan_frame(&mut self, mut frame: Option<&protobuf::StackFrame<'buffer>>) -> Result<(), Error> {
+        use self::generated::mozilla::devtools::protobuf::mod_StackFrame::OneOfStackFrameType;
+        while let Some(proto) = frame {
+            match &proto.StackFrameType {
+                OneOfStackFrameType::data(data) => {
+                    let parsed = Frame::from_protobuf(data, self)?;
+                    self.frames.insert(parsed.id, parsed);
+                    frame = data.parent.as_deref();
+                }
+                OneOfStackFrameType::ref_pb(id) => {
+                    if !self.frames.contains_key(&FrameId(*id)) {
+                        bail!("stack frame refers to unknown frame id {:?}", FrameId(*id));
+                    }
+                    break;
+                }
+                OneOfStackFrameType::None => break,
+            }
+        }
+        Ok(())
     }
 }
 
@@ -202,45 +475,84 @@ impl<'a> fmt::Debug for CoreDump<'a> {
 
 /// A type that can intern strings of type C. In practice, `Self` is always
 /// `CoreDump`, and `C` is `OneByteString` or `TwoByteString`.
-trait StringTable<'buffer, C: 'buffer + Copy + From<&'buffer [u8]>> {
-    /// Record that `string` is `self`'s next string of type `C`.
-    fn intern_string(&mut self, string: C);
+trait StringTable<'buffer, C: 'buffer + Copy + TryFrom<&'buffer [u8], Error = Error>> {
+    /// Record that `string` is `self`'s next string of type `C`, or `None` if
+    /// the next string is malformed.
+    fn intern_string(&mut self, string: Option<C>);
+
+    /// Retrieve the `C` string at index `i`, or return an error if there is no
+    /// such string.
+    fn lookup(&self, i: usize) -> Result<C, Error>;
 
-    /// Retrieve the `C` string at index `i`.
-    fn lookup(&self, i: usize) -> C;
+    /// Return the number of `C` strings interned so far.
+    fn string_count(&self) -> usize;
 
-    /// Intern `dedup`'s string, if present and given, in `self`.
-    fn intern<S>(&mut self, dedup: &S)
+    /// Intern `dedup`'s string, if present and given, in `self`. Return the
+    /// string's index in `self`'s table, if present.
+    ///
+    /// A malformed string still occupies the next index, so that back
+    /// references to the strings after it remain correct.
+    fn intern<S>(&mut self, dedup: &S) -> Result<StringIndex, Error>
     where
         S: DeduplicatedString<'buffer, C>,
     {
-        if let Some(Deduplicated::Given(string)) = dedup.get_deduplicated() {
-            self.intern_string(string);
+        match dedup.get_bytes() {
+            None => Ok(StringIndex::NONE),
+            Some(Deduplicated::Given(bytes)) => {
+                let index = StringIndex::new(self.string_count())?;
+                match C::try_from(bytes) {
+                    Ok(string) => {
+                        self.intern_string(Some(string));
+                        Ok(index)
+                    }
+                    Err(error) => {
+                        self.intern_string(None);
+                        Err(error)
+                    }
+                }
+            }
+            Some(Deduplicated::Ref(index)) => {
+                self.lookup(index)?;
+                StringIndex::new(index)
+            }
         }
     }
 
     /// Retrieve the string, if present. Consult `self`'s back reference table
     /// if needed.
-    fn get_string<D>(&self, dedup: &D) -> Option<C>
+    fn get_string<D>(&self, dedup: &D) -> Result<Option<C>, Error>
     where
         D: DeduplicatedString<'buffer, C>,
     {
-        dedup.get_deduplicated().map(|d| match d {
-            Deduplicated::Given(string) => string,
-            Deduplicated::Ref(index) => self.lookup(index),
-        })
+        dedup.get_deduplicated()?
+            .map(|d| match d {
+                Deduplicated::Given(string) => Ok(string),
+                Deduplicated::Ref(index) => self.lookup(index),
+            })
+            .transpose()
     }
 }
 
 macro_rules! impl_StringTable {
     ($table:ident, $type:ident) => {
         impl<'b> StringTable<'b, $type<'b>> for CoreDump<'b> {
-            fn intern_string(&mut self, string: $type<'b>) {
+            fn intern_string(&mut self, string: Option<$type<'b>>) {
                 self.$table.push(string);
             }
 
-            fn lookup(&self, i: usize) -> $type<'b> {
-                self.$table[i]
+            fn string_count(&self) -> usize {
+                self.$table.len()
+            }
+
+            fn lookup(&self, i: usize) -> Result<$type<'b>, Error> {
+                match self.$table.get(i) {
+                    Some(Some(string)) => Ok(*string),
+                    Some(None) => bail!("reference to string {}, which is malformed", i),
+                    None => {
+                        bail!("reference to string {}, but only {} such strings precede it",
+                              i, self.$table.len())
+                    }
+                }
             }
         }
     };
@@ -249,28 +561,28 @@ macro_rules! impl_StringTable {
 impl_StringTable!(one_byte_strings, OneByteString);
 impl_StringTable!(two_byte_strings, TwoByteString);
 
-impl<'b> From<&'b [u8]> for OneByteString<'b> {
-    fn from(bytes: &'b [u8]) -> Self {
-        OneByteString(bytes)
+impl<'b> TryFrom<&'b [u8]> for OneByteString<'b> {
+    type Error = Error;
+    fn try_from(bytes: &'b [u8]) -> Result<Self, Error> {
+        Ok(OneByteString(bytes))
     }
 }
 
-impl<'b> From<&'b [u8]> for TwoByteString<'b> {
-    fn from(bytes: &'b [u8]) -> Self {
-        TwoByteString(transmute_simple_slice(bytes))
+impl<'b> TryFrom<&'b [u8]> for TwoByteString<'b> {
+    type Error = Error;
+    fn try_from(bytes: &'b [u8]) -> Result<Self, Error> {
+        if bytes.len() % 2 == 1 {
+            bail!("two-byte string has odd length {}", bytes.len());
+        }
+        Ok(TwoByteString(bytes))
     }
 }
 
-// Turn a `&[T]` into a slice of `&[U]` of the appropriate length, where both
-// types are `Copy`. Panic if the length doesn't divide evenly.
-fn transmute_simple_slice<T, U>(slice: &[T]) -> &[U]
-where
-    T: Copy,
-    U: Copy,
-{
-    let size = ::std::mem::size_of::<U>();
-    assert!(slice.len() % size == 0);
-    unsafe { ::std::slice::from_raw_parts(slice.as_ptr() as *const U, slice.len() / size) }
+impl<'a> TwoByteString<'a> {
+    /// Return an iterator over this string's UTF-16 code units.
+    fn units(&self) -> impl Iterator<Item = u16> + 'a {
+        self.0.chunks_exact(2).map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+    }
 }
 
 /// Either a value (possibly borrowing from a core dump), or an index into a
@@ -287,18 +599,20 @@ enum Deduplicated<T> {
 ///
 /// This trait should be implemented for the `OneOfBlahOrRef` types that `pb-rs`
 /// generates for the `CoreDump.proto` `oneof` fields holding strings.
-trait DeduplicatedString<'buffer, C: 'buffer + From<&'buffer [u8]> + Copy> {
+trait DeduplicatedString<'buffer, C: 'buffer + TryFrom<&'buffer [u8], Error = Error> + Copy> {
     /// Retrieve the string as raw bytes or a back reference index.
     fn get_bytes(&self) -> Option<Deduplicated<&'buffer [u8]>>;
 
     /// Retrieve the string, if present, as either a properly typed string or a
     /// back reference index.
-    fn get_deduplicated(&self) -> Option<Deduplicated<C>> {
+    fn get_deduplicated(&self) -> Result<Option<Deduplicated<C>>, Error> {
         use self::Deduplicated::*;
-        self.get_bytes().map(|d| match d {
-            Given(bytes) => Given(C::from(bytes)),
-            Ref(index) => Ref(index),
-        })
+        self.get_bytes()
+            .map(|d| match d {
+                Given(bytes) => Ok(Given(C::try_from(bytes)?)),
+                Ref(index) => Ok(Ref(index)),
+            })
+            .transpose()
     }
 }
 
@@ -387,7 +701,7 @@ impl<'a> fmt::Debug for OneByteString<'a> {
 impl<'a> fmt::Display for TwoByteString<'a> {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> Result<(), fmt::Error> {
         let mut buf = String::new();
-        for unit in ::std::char::decode_utf16(self.0.iter().cloned()) {
+        for unit in ::std::char::decode_utf16(self.units()) {
             match unit {
                 Ok(ch) => buf.push(ch),
                 Err(_) => buf.push(::std::char::REPLACEMENT_CHARACTER),
@@ -400,7 +714,7 @@ impl<'a> fmt::Display for TwoByteString<'a> {
 impl<'a> fmt::Debug for TwoByteString<'a> {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> Result<(), fmt::Error> {
         fmt.write_char('"')?;
-        for unit in ::std::char::decode_utf16(self.0.iter().cloned()) {
+        for unit in ::std::char::decode_utf16(self.units()) {
             match unit {
                 Ok(ch) => fmt.write_char(ch)?,
                 Err(e) => write!(fmt, "\\u{{{:04x}}}", e.unpaired_surrogate())?,
@@ -411,36 +725,87 @@ impl<'a> fmt::Debug for TwoByteString<'a> {
     }
 }
 
-impl<'b> Node<'b> {
-    fn from_protobuf(proto: &protobuf::Node<'b>, dump: &CoreDump<'b>) -> Node<'b> {
-        Node {
-            id: NodeId(proto.id.unwrap()),
-            size: proto.size,
-            edges: proto
-                .edges
-                .iter()
-                .map(|pe| Edge::from_protobuf(pe, dump))
-                .collect(),
-            coarseType: CoarseType::from(proto.coarseType),
-            typeName: dump.get_string(&proto.TypeNameOrRef),
-            JSObjectClassName: dump.get_string(&proto.JSObjectClassNameOrRef),
-            scriptFilename: dump.get_string(&proto.ScriptFilenameOrRef),
-            descriptiveTypeName: dump.get_string(&proto.descriptiveTypeNameOrRef),
-        }
+// Accessors for a node's fields. Their names match those in CoreDump.proto.
+#[allow(non_snake_case)]
+impl<'a> Node<'a> {
+    /// Return the snapshot this node belongs to.
+    pub fn dump(&self) -> &'a CoreDump<'a> {
+        self.dump
+    }
+
+    pub fn id(&self) -> NodeId {
+        self.dump.nodes.ids[self.row as usize]
+    }
+
+    pub fn size(&self) -> Option<Size> {
+        self.dump.nodes.sizes[self.row as usize]
+    }
+
+    pub fn edges(&self) -> Edges<'a> {
+        Edges { dump: self.dump, indices: self.dump.nodes.edges(self.row as usize) }
+    }
+
+    pub fn allocationStack(&self) -> Option<FrameId> {
+        self.dump.nodes.allocation_stacks[self.row as usize]
+    }
+
+    pub fn coarseType(&self) -> CoarseType {
+        self.dump.nodes.coarse_types[self.row as usize]
+    }
+
+    pub fn typeName(&self) -> Option<TwoByteString<'a>> {
+        self.dump.two_byte_string(self.dump.nodes.type_names[self.row as usize])
+    }
+
+    pub fn JSObjectClassName(&self) -> Option<OneByteString<'a>> {
+        self.dump.one_byte_string(self.dump.nodes.js_object_class_names[self.row as usize])
+    }
+
+    pub fn scriptFilename(&self) -> Option<OneByteString<'a>> {
+        self.dump.one_byte_string(self.dump.nodes.script_filenames[self.row as usize])
+    }
+
+    pub fn descriptiveTypeName(&self) -> Option<TwoByteString<'a>> {
+        self.dump.two_byte_string(self.dump.nodes.descriptive_type_names[self.row as usize])
+    }
+}
+
+impl<'b> CoreDump<'b> {
+    // Interning checks every index before it goes in the node table, so these
+    // lookups can't fail.
+
+    fn one_byte_string(&self, index: StringIndex) -> Option<OneByteString<'b>> {
+        index.get().and_then(|i| self.one_byte_strings[i])
+    }
+
+    fn two_byte_string(&self, index: StringIndex) -> Option<TwoByteString<'b>> {
+        index.get().and_then(|i| self.two_byte_strings[i])
     }
 }
 
-impl<'b> Default for Node<'b> {
-    fn default() -> Node<'b> {
-        Node {
-            id: NodeId(0),
-            size: None,
-            edges: Vec::new(),
-            coarseType: CoarseType::Other,
-            typeName: None,
-            JSObjectClassName: None,
-            scriptFilename: None,
-            descriptiveTypeName: None,
+impl<'a> Iterator for Edges<'a> {
+    type Item = Edge<'a>;
+
+    fn next(&mut self) -> Option<Edge<'a>> {
+        self.indices.next().map(|i| self.dump.edge_at(i))
+    }
+
+    fn nth(&mut self, n: usize) -> Option<Edge<'a>> {
+        self.indices.nth(n).map(|i| self.dump.edge_at(i))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.indices.size_hint()
+    }
+}
+
+impl<'a> ExactSizeIterator for Edges<'a> {}
+
+impl<'a> CoreDump<'a> {
+    fn edge_at(&self, i: usize) -> Edge<'a> {
+        Edge {
+            referent: self.nodes.edge_referents[i],
+            name: self.two_byte_string(self.nodes.edge_names[i]),
         }
     }
 }
@@ -454,23 +819,59 @@ fn optional_field<T: fmt::Debug>(dbg: &mut fmt::DebugStruct, label: &str, opt: &
 impl<'b> fmt::Debug for Node<'b> {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> Result<(), fmt::Error> {
         let mut d = fmt.debug_struct("Node");
-        d.field("id", &self.id);
-        d.field("coarseType", &self.coarseType);
-        optional_field(&mut d, "typeName", &self.typeName);
-        optional_field(&mut d, "size", &self.size);
-        optional_field(&mut d, "JSObjectClassName", &self.JSObjectClassName);
-        optional_field(&mut d, "scriptFilename", &self.scriptFilename);
-        optional_field(&mut d, "descriptiveTypeName", &self.descriptiveTypeName);
+        d.field("id", &self.id());
+        d.field("coarseType", &self.coarseType());
+        optional_field(&mut d, "typeName", &self.typeName());
+        optional_field(&mut d, "size", &self.size());
+        optional_field(&mut d, "JSObjectClassName", &self.JSObjectClassName());
+        optional_field(&mut d, "scriptFilename", &self.scriptFilename());
+        optional_field(&mut d, "descriptiveTypeName", &self.descriptiveTypeName());
+        optional_field(&mut d, "allocationStack", &self.allocationStack());
         d.finish()
     }
 }
 
-impl<'b> Edge<'b> {
-    fn from_protobuf(proto: &protobuf::Edge<'b>, dump: &CoreDump<'b>) -> Edge<'b> {
-        Edge {
-            referent: proto.referent.map(NodeId),
-            name: dump.get_string(&proto.EdgeNameOrRef),
-        }
+impl<'b> Frame<'b> {
+    fn from_protobuf(data: &protobuf::mod_StackFrame::Data<'b>, dump: &CoreDump<'b>)
+                     -> Result<Frame<'b>, Error>
+    {
+        let id = match data.id {
+            None => bail!("stack frame has no id"),
+            Some(id) => FrameId(id),
+        };
+        Ok(Frame {
+            id,
+            source: dump.get_string(&data.SourceOrRef)?,
+            line: data.line,
+            column: data.column,
+            functionDisplayName: dump.get_string(&data.FunctionDisplayNameOrRef)?,
+            isSystem: data.isSystem,
+            isSelfHosted: data.isSelfHosted,
+            parent: data.parent.as_deref().and_then(frame_id),
+        })
+    }
+}
+
+/// Return the id of the frame `proto` either carries or refers to.
+fn frame_id(proto: &protobuf::StackFrame) -> Option<FrameId> {
+    use self::generated::mozilla::devtools::protobuf::mod_StackFrame::OneOfStackFrameType;
+    match &proto.StackFrameType {
+        OneOfStackFrameType::data(data) => data.id.map(FrameId),
+        OneOfStackFrameType::ref_pb(id) => Some(FrameId(*id)),
+        OneOfStackFrameType::None => None,
+    }
+}
+
+impl<'b> fmt::Debug for Frame<'b> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        let mut d = fmt.debug_struct("Frame");
+        d.field("id", &self.id);
+        optional_field(&mut d, "functionDisplayName", &self.functionDisplayName);
+        optional_field(&mut d, "source", &self.source);
+        optional_field(&mut d, "line", &self.line);
+        optional_field(&mut d, "column", &self.column);
+        optional_field(&mut d, "parent", &self.parent);
+        d.finish()
     }
 }
 
@@ -489,16 +890,23 @@ impl fmt::Debug for NodeId {
     }
 }
 
-impl From<u32> for CoarseType {
-    fn from(n: u32) -> CoarseType {
-        match n {
+impl fmt::Debug for FrameId {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        write!(fmt, "0x{:x}", self.0)
+    }
+}
+
+impl TryFrom<u32> for CoarseType {
+    type Error = Error;
+    fn try_from(n: u32) -> Result<CoarseType, Error> {
+        Ok(match n {
             0 => CoarseType::Other,
             1 => CoarseType::Object,
             2 => CoarseType::Script,
             3 => CoarseType::String,
             4 => CoarseType::DOMNode,
-            _ => panic!("bad coarse type value {:?}", n),
-        }
+            _ => bail!("bad coarse type value {:?}", n),
+        })
     }
 }
 
@@ -507,3 +915,169 @@ impl From<CoarseType> for String {
         format!("{:?}", c)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::{CoreDump, NodeId};
+    use std::path::Path;
+
+    #[test]
+    fn frame_references() {
+        use super::test_utils::{allocation_stack, delimited, frame, node, snapshot, uint};
+        use super::FrameId;
+
+        // A frame's first appearance gives its data; later appearances, as a
+        // stack or as a parent, are `ref`s to its id.
+        let root = [node(1, 8, &[], &[])];
+        let outer = [node(2, 8, &[], &allocation_stack(10, "inner", Some(&frame(11, "outer", None))))];
+        let refs = [node(3, 8, &[], &allocation_stack(12, "other", Some(&uint(2, 11)))),
+                    node(4, 8, &[], &delimited(6, &uint(2, 10)))];
+        let bytes = snapshot(&[&root[..], &outer, &refs].concat());
+        let dump = CoreDump::from_bytes(Path::new("frames"), &bytes).expect("load failed");
+        let stack = |id| dump.get_node(NodeId(id)).unwrap().allocationStack();
+        assert_eq!(stack(3), Some(FrameId(12)));
+        assert_eq!(stack(4), Some(FrameId(10)));
+        assert_eq!(dump.get_frame(FrameId(12)).unwrap().parent, Some(FrameId(11)));
+        let parent = dump.get_frame(FrameId(11)).unwrap();
+        assert_eq!(parent.functionDisplayName.map(|name| name.to_string()), Some("outer".to_owned()));
+
+        // A `ref` to a frame that hasn't appeared yet is an error, unless we're
+        // being lenient, in which case the node is skipped.
+        let bytes = snapshot(&[&root[..], &refs, &outer].concat());
+        let error = CoreDump::from_bytes(Path::new("dangling"), &bytes).expect_err("strict load should fail");
+        assert!(format!("{:#}", error).contains("stack frame refers to unknown frame id 0xb"));
+        let dump = CoreDump::from_bytes_lenient(Path::new("dangling"), &bytes).expect("lenient load failed");
+        assert!(!dump.has_node(NodeId(3)));
+        assert!(!dump.has_node(NodeId(4)));
+        assert!(dump.has_node(NodeId(2)));
+    }
+
+    #[test]
+    fn malformed_nodes() {
+        use super::test_utils::{node, snapshot, uint};
+
+        let root = node(1, 8, &[], &[]);
+        let nodes = [
+            root.clone(),
+            node(2, 8, &[(1, "a")], &uint(9, 9)), // unknown coarse type 9
+            node(3, 8, &[(2, "b")], &[]),
+        ];
+        let bytes = [snapshot(&nodes), vec![5, 0x08]].concat(); // truncated
+        let path = Path::new("malformed");
+
+        let error = CoreDump::from_bytes(path, &bytes).expect_err("strict load should fail");
+        let offset = snapshot(&[root]).len();
+        assert!(format!("{:#}", error).contains(&format!("at offset {:x}:", offset)));
+
+        let dump = CoreDump::from_bytes_lenient(path, &bytes).expect("lenient load failed");
+        assert!(dump.has_node(NodeId(1)));
+        assert!(!dump.has_node(NodeId(2)));
+        assert!(dump.has_node(NodeId(3)));
+        assert_eq!(dump.warnings.len(), 2);
+
+        // The skipped node's edge must not end up attached to its successor.
+        let referents: Vec<_> = dump.get_node(NodeId(3)).unwrap().edges()
+            .map(|edge| edge.referent)
+            .collect();
+        assert_eq!(referents, vec![Some(NodeId(2))]);
+    }
+
+    #[test]
+    fn check() {
+        use super::test_utils::{node, snapshot, uint};
+
+        let bytes = snapshot(&[
+            node(1, 8, &[(2, "a"), (9, "absent")], &[]),
+            node(2, 8, &[], &[]),
+            uint(1, 3), // id 3, no size, unreachable
+            node(2, 16, &[], &[]),
+        ]);
+        let path = Path::new("check");
+
+        let dump = CoreDump::from_bytes(path, &bytes).expect("load failed");
+        assert_eq!(dump.warnings.len(), 1);
+        assert!(dump.warnings[0].contains("same id as an earlier node, 0x2"));
+        assert_eq!(dump.get_node(NodeId(2)).unwrap().size(), Some(16));
+
+        let problems: Vec<String> = dump.check().iter().map(ToString::to_string).collect();
+        assert_eq!(problems, vec![
+            "edges referring to nodes not in the snapshot: 1 (0x1 -> 0x9)",
+            "nodes with no size: 1 (0x3)",
+            "nodes unreachable from the root: 1 (0x3)",
+        ]);
+    }
+
+    #[test]
+    fn skipped_node_strings() {
+        use super::test_utils::{allocation_stack, delimited, node, snapshot, two_byte, uint};
+
+        // Two-byte strings are numbered in order of appearance: the root's
+        // edge names are 0 through 3. Node 2's frame's parent is unknown, and
+        // node 4's type name has an odd length, so a lenient load skips them,
+        // but their strings still take up indices 4 through 7.
+        let bytes = snapshot(&[
+            node(1, 8, &[(2, "a"), (3, "b"), (4, "c"), (5, "d")], &[]),
+            node(2, 8, &[], &[allocation_stack(10, "f", Some(&uint(2, 99))),
+                              delimited(12, &two_byte("desc"))].concat()),
+            node(3, 8, &[], &uint(13, 5)),
+            node(4, 8, &[], &[delimited(2, b"odd"), delimited(12, &two_byte("later"))].concat()),
+            node(5, 8, &[], &uint(13, 7)),
+            node(6, 8, &[], &uint(3, 6)), // refers to the odd-length string
+        ]);
+        let path = Path::new("skipped");
+        CoreDump::from_bytes(path, &bytes).expect_err("strict load should fail");
+
+        let dump = CoreDump::from_bytes_lenient(path, &bytes).expect("lenient load failed");
+        assert_eq!(dump.warnings.len(), 3);
+        for id in [2, 4, 6] {
+            assert!(!dump.has_node(NodeId(id)));
+        }
+        let name = |id| dump.get_node(NodeId(id)).unwrap().descriptiveTypeName().map(|s| s.to_string());
+        assert_eq!(name(3), Some("desc".to_owned()));
+        assert_eq!(name(5), Some("later".to_owned()));
+    }
+
+    #[test]
+    fn skipped_node_frames() {
+        use super::test_utils::{allocation_stack, delimited, frame, node, snapshot, uint};
+        use super::FrameId;
+
+        // Node 2's type name has an odd length, so a lenient load skips it, but
+        // node 3's stack is a `ref` to the frame that node 2 introduced.
+        let bytes = snapshot(&[
+            node(1, 8, &[], &[]),
+            node(2, 8, &[], &[delimited(2, b"odd"),
+                              allocation_stack(10, "inner", Some(&frame(11, "outer", None)))].concat()),
+            node(3, 8, &[], &delimited(6, &uint(2, 10))),
+        ]);
+        let dump = CoreDump::from_bytes_lenient(Path::new("skipped"), &bytes).expect("lenient load failed");
+        assert_eq!(dump.warnings.len(), 1);
+        assert!(!dump.has_node(NodeId(2)));
+        assert_eq!(dump.get_node(NodeId(3)).unwrap().allocationStack(), Some(FrameId(10)));
+        assert_eq!(dump.get_frame(FrameId(10)).unwrap().parent, Some(FrameId(11)));
+    }
+
+    #[test]
+    fn node_order() {
+        use super::test_utils::{node, snapshot};
+
+        let mut nodes: Vec<_> = [4, 9, 2, 7].iter().map(|&id| node(id, 8, &[], &[])).collect();
+        // id 4 again, with an edge to 9, replacing the first
+        nodes.push(node(4, 8, &[(9, "a")], &[]));
+        let bytes = snapshot(&nodes);
+        let path = Path::new("order");
+        for dump in [CoreDump::from_bytes(path, &bytes), CoreDump::from_bytes_lenient(path, &bytes)] {
+            let dump = dump.expect("load failed");
+            let ids: Vec<u64> = dump.nodes().map(|node| node.id().0).collect();
+            assert_eq!(ids, vec![2, 4, 7, 9]);
+
+            // The replaced node leaves nothing behind in the table.
+            assert_eq!(dump.nodes.ids.len(), 4);
+            assert_eq!(dump.nodes.edge_referents, vec![Some(NodeId(9))]);
+            let referents: Vec<_> = dump.get_node(NodeId(4)).unwrap().edges()
+                .map(|edge| edge.referent)
+                .collect();
+            assert_eq!(referents, vec![Some(NodeId(9))]);
+        }
+    }
+}
@@ -0,0 +1,65 @@
+//! An index of each node's incoming edges.
+//!
+//! A core dump only records each node's outgoing edges, so finding the nodes
+//! that refer to a given node means scanning every node in the dump. When a
+//! query needs to do this repeatedly, it's cheaper to scan once and build a
+//! table of incoming edges.
+
+use super::CoreDump;
+
+/// For each node, the edges that refer to it.
+///
+/// Nodes and edges are identified by their rows and indices in the dump's node
+/// table; `CoreDump` translates these back into `Node`s and `Edge`s.
+pub struct Referrers {
+    /// Where each row's incoming edges begin in `incoming`. This has one more
+    /// element than there are rows; the last is the length of `incoming`.
+    starts: Vec<u32>,
+
+    /// Every edge with a referent present in the dump, grouped by referent.
+    /// Each edge is given as the row of the node it belongs to and its index
+    /// in the node table's edge columns.
+    incoming: Vec<(u32, u32)>,
+}
+
+impl Referrers {
+    pub fn from_dump(dump: &CoreDump) -> Referrers {
+        let count = dump.nodes.ids.len();
+
+        // Visit rows in order of id, so that each node's referrers are listed
+        // in order of id too.
+        let edges = || {
+            dump.order.iter().flat_map(move |&row| {
+                dump.nodes.edges(row as usize).filter_map(move |edge| {
+                    let referent = dump.nodes.edge_referents[edge]?;
+                    let &referent = dump.rows.get(&referent)?;
+                    Some((referent, (row, edge as u32)))
+                })
+            })
+        };
+
+        let mut starts = vec![0; count + 1];
+        for (referent, _) in edges() {
+            starts[referent as usize + 1] += 1;
+        }
+        for row in 0..count {
+            starts[row + 1] += starts[row];
+        }
+
+        let mut next = starts.clone();
+        let mut incoming = vec![(0, 0); starts[count] as usize];
+        for (referent, edge) in edges() {
+            incoming[next[referent as usize] as usize] = edge;
+            next[referent as usize] += 1;
+        }
+
+        Referrers { starts, incoming }
+    }
+
+    /// Return the incoming edges of the node in `row`, each given as the row of
+    /// the node it belongs to and its index in the node table's edge columns.
+    pub fn get(&self, row: u32) -> &[(u32, u32)] {
+        let row = row as usize;
+        &self.incoming[self.starts[row] as usize .. self.starts[row + 1] as usize]
+    }
+}
@@ -0,0 +1,169 @@
+//! Columnar storage for a `CoreDump`'s nodes and edges.
+//!
+//! Content process snapshots can run to several gigabytes, holding tens of
+//! millions of nodes and many more edges, so how we store them matters. Rather
+//! than a struct per node, each with its own vector of edges, we keep one dense
+//! vector per field, indexed by the node's 'row'. All the nodes' edges live in
+//! a single set of vectors, in 'compressed sparse row' form: the edges of the
+//! node in row `i` are those at indices `edge_starts[i] .. edge_starts[i + 1]`.
+//! Strings are stored as indices into the dump's string tables.
+//!
+//! The `Node` type is just a handle on a row; its accessor methods look up
+//! the fields in these vectors.
+
+use anyhow::{anyhow, Error};
+
+use super::{CoarseType, FrameId, NodeId, Size};
+
+use std::ops::Range;
+
+/// An index into one of a `CoreDump`'s string tables, or `StringIndex::NONE`
+/// if the string is absent.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct StringIndex(u32);
+
+impl StringIndex {
+    pub const NONE: StringIndex = StringIndex(u32::MAX);
+
+    pub fn new(index: usize) -> Result<StringIndex, Error> {
+        match u32::try_from(index) {
+            Ok(index) if index != u32::MAX => Ok(StringIndex(index)),
+            _ => Err(anyhow!("too many strings in snapshot")),
+        }
+    }
+
+    pub fn get(self) -> Option<usize> {
+        if self == StringIndex::NONE {
+            None
+        } else {
+            Some(self.0 as usize)
+        }
+    }
+}
+
+/// The fields of a single node, other than its edges, for adding to a
+/// `NodeTable`.
+pub struct Row {
+    pub id: NodeId,
+    pub size: Option<Size>,
+    pub allocation_stack: Option<FrameId>,
+    pub coarse_type: CoarseType,
+    pub type_name: StringIndex,
+    pub js_object_class_name: StringIndex,
+    pub script_filename: StringIndex,
+    pub descriptive_type_name: StringIndex,
+}
+
+/// All the nodes and edges of a `CoreDump`, column by column.
+pub struct NodeTable {
+    pub ids: Vec<NodeId>,
+    pub sizes: Vec<Option<Size>>,
+    pub allocation_stacks: Vec<Option<FrameId>>,
+    pub coarse_types: Vec<CoarseType>,
+    pub type_names: Vec<StringIndex>,
+    pub js_object_class_names: Vec<StringIndex>,
+    pub script_filenames: Vec<StringIndex>,
+    pub descriptive_type_names: Vec<StringIndex>,
+
+    /// Where each row's edges begin in `edge_referents` and `edge_names`. This
+    /// has one more element than there are rows; the last is where the next
+    /// row's edges would begin.
+    edge_starts: Vec<u32>,
+
+    pub edge_referents: Vec<Option<NodeId>>,
+    pub edge_names: Vec<StringIndex>,
+}
+
+impl NodeTable {
+    pub fn new() -> NodeTable {
+        NodeTable {
+            ids: Vec::new(),
+            sizes: Vec::new(),
+            allocation_stacks: Vec::new(),
+            coarse_types: Vec::new(),
+            type_names: Vec::new(),
+            js_object_class_names: Vec::new(),
+            script_filenames: Vec::new(),
+            descriptive_type_names: Vec::new(),
+            edge_starts: vec![0],
+            edge_referents: Vec::new(),
+            edge_names: Vec::new(),
+        }
+    }
+
+    /// Add an edge to the row that the next call to `push_row` will add.
+    pub fn push_edge(&mut self, referent: Option<NodeId>, name: StringIndex) {
+        self.edge_referents.push(referent);
+        self.edge_names.push(name);
+    }
+
+    /// Discard any edges pushed since the last call to `push_row`, when the
+    /// node they belong to turns out to be malformed.
+    pub fn discard_edges(&mut self) {
+        let start = *self.edge_starts.last().unwrap() as usize;
+        self.edge_referents.truncate(start);
+        self.edge_names.truncate(start);
+    }
+
+    /// Add `row`, owning all the edges pushed since the last call, and return
+    /// its row number.
+    pub fn push_row(&mut self, row: Row) -> Result<u32, Error> {
+        let number = u32::try_from(self.ids.len())
+            .map_err(|_| anyhow!("too many nodes in snapshot"))?;
+        let edges_end = u32::try_from(self.edge_referents.len())
+            .map_err(|_| anyhow!("too many edges in snapshot"))?;
+        self.ids.push(row.id);
+        self.sizes.push(row.size);
+        self.allocation_stacks.push(row.allocation_stack);
+        self.coarse_types.push(row.coarse_type);
+        self.type_names.push(row.type_name);
+        self.js_object_class_names.push(row.js_object_class_name);
+        self.script_filenames.push(row.script_filename);
+        self.descriptive_type_names.push(row.descriptive_type_name);
+        self.edge_starts.push(edges_end);
+        Ok(number)
+    }
+
+    /// Return the range of indices in the edge columns holding `row`'s edges.
+    pub fn edges(&self, row: usize) -> Range<usize> {
+        self.edge_starts[row] as usize .. self.edge_starts[row + 1] as usize
+    }
+
+    /// Return a table holding just the rows listed in `rows`, renumbered in
+    /// that order from zero, along with their edges.
+    pub fn select_rows(&self, rows: &[u32]) -> Result<NodeTable, Error> {
+        let mut table = NodeTable::new();
+        for &row in rows {
+            let row = row as usize;
+            for edge in self.edges(row) {
+                table.push_edge(self.edge_referents[edge], self.edge_names[edge]);
+            }
+            table.push_row(Row {
+                id: self.ids[row],
+                size: self.sizes[row],
+                allocation_stack: self.allocation_stacks[row],
+                coarse_type: self.coarse_types[row],
+                type_name: self.type_names[row],
+                js_object_class_name: self.js_object_class_names[row],
+                script_filename: self.script_filenames[row],
+                descriptive_type_name: self.descriptive_type_names[row],
+            })?;
+        }
+        Ok(table)
+    }
+
+    /// Release the spare capacity the vectors accumulated while loading.
+    pub fn shrink_to_fit(&mut self) {
+        self.ids.shrink_to_fit();
+        self.sizes.shrink_to_fit();
+        self.allocation_stacks.shrink_to_fit();
+        self.coarse_types.shrink_to_fit();
+        self.type_names.shrink_to_fit();
+        self.js_object_class_names.shrink_to_fit();
+        self.script_filenames.shrink_to_fit();
+        self.descriptive_type_names.shrink_to_fit();
+        self.edge_starts.shrink_to_fit();
+        self.edge_referents.shrink_to_fit();
+        self.edge_names.shrink_to_fit();
+    }
+}
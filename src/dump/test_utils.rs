@@ -0,0 +1,82 @@
+#![cfg(test)]
+
+//! Building small heap snapshots by hand, in the protobuf format Firefox
+//! writes, for tests.
+
+/// Return `n` as a protobuf varint.
+pub fn varint(mut n: u64) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    while n >= 0x80 {
+        bytes.push(n as u8 | 0x80);
+        n >>= 7;
+    }
+    bytes.push(n as u8);
+    bytes
+}
+
+/// Return field `number` holding the integer `n`.
+pub fn uint(number: u64, n: u64) -> Vec<u8> {
+    let mut bytes = varint(number << 3);
+    bytes.extend(varint(n));
+    bytes
+}
+
+/// Return field `number` holding the length-delimited `contents`.
+pub fn delimited(number: u64, contents: &[u8]) -> Vec<u8> {
+    let mut bytes = varint(number << 3 | 2);
+    bytes.extend(varint(contents.len() as u64));
+    bytes.extend_from_slice(contents);
+    bytes
+}
+
+/// Return `text` as the little-endian UTF-16 that two-byte strings hold.
+pub fn two_byte(text: &str) -> Vec<u8> {
+    text.encode_utf16().flat_map(u16::to_le_bytes).collect()
+}
+
+/// Return a node with the given `id`, `size`, and edges, each given as a
+/// referent and a name. Append `rest`, which should be further fields.
+pub fn node(id: u64, size: u64, edges: &[(u64, &str)], rest: &[u8]) -> Vec<u8> {
+    let mut bytes = uint(1, id);
+    bytes.extend(uint(4, size));
+    for &(referent, name) in edges {
+        let mut edge = uint(1, referent);
+        edge.extend(delimited(2, &two_byte(name)));
+        bytes.extend(delimited(5, &edge));
+    }
+    bytes.extend_from_slice(rest);
+    bytes
+}
+
+/// Return a node's `allocationStack` field, holding a frame with the given
+/// `id` and `functionDisplayName`, whose parent is `parent`, if given.
+pub fn allocation_stack(id: u64, name: &str, parent: Option<&[u8]>) -> Vec<u8> {
+    delimited(6, &frame(id, name, parent))
+}
+
+/// Return a stack frame with the given `id` and `functionDisplayName`, whose
+/// parent is `parent`, if given.
+pub fn frame(id: u64, name: &str, parent: Option<&[u8]>) -> Vec<u8> {
+    let mut data = uint(1, id);
+    if let Some(parent) = parent {
+        data.extend(delimited(2, parent));
+    }
+    data.extend(delimited(7, &two_byte(name)));
+    delimited(1, &data)
+}
+
+/// Return a snapshot holding `nodes`, the first of which is the root.
+pub fn snapshot(nodes: &[Vec<u8>]) -> Vec<u8> {
+    let mut bytes = delimited_message(&uint(1, 1)); // metadata: timestamp 1
+    for node in nodes {
+        bytes.extend(delimited_message(node));
+    }
+    bytes
+}
+
+/// Return `fields` as a length-delimited message, as snapshots hold them.
+pub fn delimited_message(fields: &[u8]) -> Vec<u8> {
+    let mut bytes = varint(fields.len() as u64);
+    bytes.extend_from_slice(fields);
+    bytes
+}
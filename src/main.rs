@@ -7,47 +7,205 @@ mod id_vec;
 
 // extern crate uses
 use anyhow::{bail, Context, Error};
-use memmap::Mmap;
 
 // intra-crate modules
 mod dump;
 mod query;
+mod repl;
+mod report;
 
 // intra-crate uses
-use crate::dump::CoreDump;
+use crate::dump::{CoreDump, SnapshotBuffer};
+use crate::report::{explain_query_error, report_skipped_edges};
 
 // std uses
-use std::fs::File;
+use std::ffi::{OsStr, OsString};
 use std::path::Path;
 
+const USAGE: &str = "\
+Usage: fxsnapshot [OPTIONS] FILE [QUERY]
+       fxsnapshot [OPTIONS] diff OLD NEW [QUERY]
+       fxsnapshot check FILE
+Options:
+    --load DEFS.fxq      load definitions from DEFS.fxq
+    --format FORMAT      write results as 'text' (the default), 'json', 'jsonl',
+                         'csv', or 'tsv'
+    --columns A,B,...    for 'csv' and 'tsv', the fields to write in each row
+    --lenient            skip malformed nodes in snapshots, and edges to
+                         absent nodes, with a warning, rather than failing";
+
 fn run() -> Result<(), Error> {
     let args = std::env::args_os().skip(1).collect::<Vec<_>>();
-    if args.len() != 2 {
-        bail!("Usage: fxsnapshot FILE QUERY");
+
+    let (options, args) = parse_options(&args)?;
+
+    // Load the prelude, and any files of definitions given on the command line.
+    let mut library = query::Library::with_prelude();
+    for path in options.load {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read definitions from '{}'", path.display()))?;
+        library.define(&text)
+            .map_err(|error| query::show_in_query(error.into(), &text))
+            .with_context(|| format!("In definitions file '{}'", path.display()))?;
+    }
+
+    let mut format = match options.format {
+        Some(name) => name.to_string_lossy().parse()?,
+        None => query::Format::default(),
+    };
+    if let Some(columns) = options.columns {
+        format = format.with_columns(columns.to_string_lossy().split(',').map(str::to_owned).collect())?;
     }
 
-    // Compile the query given on the command line.
-    let query_text = args[1].to_string_lossy().into_owned();
-    let query = query::compile(&query_text)?;
+    let (baseline_path, path, query_text) = match parse_command(args)? {
+        Command::Check(path) => return check_snapshot(path),
+        Command::Query { baseline, path, query } => (baseline, path, query),
+    };
 
-    // Open and index the core dump file.
-    let path = Path::new(&args[0]);
-    let file =
-        File::open(path).context(format!("Failed to open snapshot '{}':", path.display()))?;
-    let mmap = unsafe { Mmap::map(&file)? };
-    let bytes = &mmap[..];
-    let dump = CoreDump::from_bytes(path, bytes)?;
+    // Compile the query given on the command line, if any, before spending
+    // time loading the snapshot.
+    let query_text = query_text.map(|text| text.to_string_lossy());
+    let query = query_text.as_deref()
+        .map(|text| {
+            library.compile(text).map_err(|error| query::show_in_query(error.into(), text))
+        })
+        .transpose()?;
 
-    // Run the query, and print the result to stdout.
-    let context = query::Context::from_dump(&dump);
+    // Open and index the core dump file, and the baseline, if any.
+    let load = |path, buffer| -> Result<CoreDump, Error> {
+        let dump = if options.lenient {
+            CoreDump::from_bytes_lenient(path, buffer)?
+        } else {
+            CoreDump::from_bytes(path, buffer)?
+        };
+        for warning in &dump.warnings {
+            eprintln!("warning: {}", warning);
+        }
+        Ok(dump)
+    };
+
+    let buffer = SnapshotBuffer::open(path)?;
+    let dump = load(path, &buffer)?;
+
+    let baseline_buffer = baseline_path.map(SnapshotBuffer::open).transpose()?;
+    let baseline = match (baseline_path, &baseline_buffer) {
+        (Some(path), Some(buffer)) => Some(load(path, buffer)?),
+        _ => None,
+    };
+
+    let mut context = match &baseline {
+        Some(baseline) => query::Context::with_baseline(&dump, baseline),
+        None => query::Context::from_dump(&dump),
+    };
+    if options.lenient {
+        context = context.with_dangling_edges(query::DanglingEdges::Skip(Default::default()));
+    }
+
+    // Without a query, read queries from the terminal.
+    let (query, query_text) = match (query, query_text) {
+        (Some(query), Some(query_text)) => (query, query_text),
+        _ => return repl::run(&context, library, &format),
+    };
+
+    // Run the query, and print the result to stdout. Since streams are lazy,
+    // errors in the query can turn up while printing, too.
     let activation_base = query::ActivationBase::from_context(&context);
     let activation = query::Activation::for_eval(&activation_base);
-    let result = query.run(&activation, &context)?;
+    let run = || -> Result<(), Error> {
+        let result = query.run(&activation, &context)?;
+        let stdout = std::io::stdout();
+        result.write_formatted(&format, &context, &mut stdout.lock())?;
+        report_skipped_edges(&context);
+        Ok(())
+    };
+    run().map_err(|error| explain_query_error(error, &query_text))
+}
+
+/// The options given on the command line, before the command.
+#[derive(Debug, Default, PartialEq)]
+struct Options<'a> {
+    /// Files of definitions to load, in order.
+    load: Vec<&'a Path>,
+    format: Option<&'a OsStr>,
+    columns: Option<&'a OsStr>,
+    lenient: bool,
+}
+
+/// Parse the options at the front of `args`, and return them along with the
+/// arguments that follow them.
+fn parse_options(mut args: &[OsString]) -> Result<(Options, &[OsString]), Error> {
+    let mut options = Options::default();
+    while let [flag, rest @ ..] = args {
+        // Anything that doesn't look like an option begins the command.
+        let Some(flag) = flag.to_str().filter(|flag| flag.starts_with("--")) else { break };
+        if flag == "--lenient" {
+            options.lenient = true;
+            args = rest;
+            continue;
+        }
+        if !["--load", "--format", "--columns"].contains(&flag) {
+            bail!("unknown option '{}'\n{}", flag, USAGE);
+        }
+        let [value, rest @ ..] = rest else {
+            bail!("option '{}' needs a value\n{}", flag, USAGE);
+        };
+        match flag {
+            "--load" => options.load.push(Path::new(value)),
+            "--format" => options.format = Some(value),
+            "--columns" => options.columns = Some(value),
+            _ => unreachable!(),
+        }
+        args = rest;
+    }
+    Ok((options, args))
+}
 
-    let stdout = std::io::stdout();
-    result .top_write(&mut stdout.lock())?;
-    println!();
+/// What the command line asks for, after the options.
+#[derive(Debug, PartialEq)]
+enum Command<'a> {
+    /// `check FILE`
+    Check(&'a Path),
 
+    /// `FILE [QUERY]`, or `diff OLD NEW [QUERY]`, in which `OLD` is the
+    /// baseline and the query runs against `NEW`.
+    Query { baseline: Option<&'a Path>, path: &'a Path, query: Option<&'a OsStr> },
+}
+
+fn parse_command(args: &[OsString]) -> Result<Command, Error> {
+    fn query<'a>(baseline: Option<&'a OsString>, path: &'a OsString, query: Option<&'a OsString>) -> Command<'a> {
+        Command::Query {
+            baseline: baseline.map(Path::new),
+            path: Path::new(path),
+            query: query.map(OsString::as_os_str),
+        }
+    }
+    Ok(match args {
+        [check, path] if check == "check" => Command::Check(Path::new(path)),
+        [diff, baseline, path] if diff == "diff" => query(Some(baseline), path, None),
+        [diff, baseline, path, text] if diff == "diff" => query(Some(baseline), path, Some(text)),
+        [path] => query(None, path, None),
+        [path, text] => query(None, path, Some(text)),
+        _ => bail!(USAGE),
+    })
+}
+
+/// Load the snapshot at `path` leniently, and report everything wrong with it.
+/// Fail if there were any problems.
+fn check_snapshot(path: &Path) -> Result<(), Error> {
+    let buffer = SnapshotBuffer::open(path)?;
+    let dump = CoreDump::from_bytes_lenient(path, &buffer)?;
+    let problems = dump.check();
+    println!("{}: {} nodes", path.display(), dump.nodes().count());
+    for warning in &dump.warnings {
+        println!("{}", warning);
+    }
+    for problem in &problems {
+        println!("{}", problem);
+    }
+    if !dump.warnings.is_empty() || !problems.is_empty() {
+        bail!("{}: snapshot has problems", path.display());
+    }
+    println!("no problems found");
     Ok(())
 }
 
@@ -57,3 +215,55 @@ fn main() {
         std::process::exit(1);
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::{parse_command, parse_options, Command, Options};
+    use std::ffi::{OsStr, OsString};
+    use std::path::Path;
+
+    #[test]
+    fn commands() {
+        let args = |text: &str| text.split(' ').map(OsString::from).collect::<Vec<_>>();
+        let query = |baseline: Option<&'static str>, path: &'static str, query: Option<&'static str>| {
+            Command::Query {
+                baseline: baseline.map(Path::new),
+                path: Path::new(path),
+                query: query.map(OsStr::new),
+            }
+        };
+
+        // In `diff OLD NEW`, the first snapshot is the baseline.
+        assert_eq!(parse_command(&args("diff old.snap new.snap added")).unwrap(),
+                   query(Some("old.snap"), "new.snap", Some("added")));
+        assert_eq!(parse_command(&args("diff old.snap new.snap")).unwrap(),
+                   query(Some("old.snap"), "new.snap", None));
+        assert_eq!(parse_command(&args("new.snap root")).unwrap(), query(None, "new.snap", Some("root")));
+        assert_eq!(parse_command(&args("check x.snap")).unwrap(), Command::Check(Path::new("x.snap")));
+        assert!(parse_command(&args("diff a b c d")).is_err());
+    }
+    #[test]
+    fn options() {
+        let args = |text: &str| text.split(' ').map(OsString::from).collect::<Vec<_>>();
+
+        let all = args("--load a.fxq --lenient --format csv --load b.fxq --columns id,size x.snap root");
+        let (options, rest) = parse_options(&all).unwrap();
+        assert_eq!(options, Options {
+            load: vec![Path::new("a.fxq"), Path::new("b.fxq")],
+            format: Some(OsStr::new("csv")),
+            columns: Some(OsStr::new("id,size")),
+            lenient: true,
+        });
+        assert_eq!(rest, &args("x.snap root")[..]);
+
+        let none = args("diff old.snap new.snap");
+        assert_eq!(parse_options(&none).unwrap(), (Options::default(), &none[..]));
+
+        // Misspelled options, and options missing their values, must not be
+        // mistaken for the snapshot's path.
+        let error = parse_options(&args("--lenent x.snap")).expect_err("unknown option should fail");
+        assert!(error.to_string().starts_with("unknown option '--lenent'"));
+        let error = parse_options(&args("--lenient --format")).expect_err("missing value should fail");
+        assert!(error.to_string().starts_with("option '--format' needs a value"));
+    }
+}
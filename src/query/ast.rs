@@ -1,12 +1,24 @@
 //! A query expression, syntactically well-formed.
 
 use crate::id_vec::IdVecIndex;
+use super::walkers::{Walkable, WalkerMut};
 use std::fmt;
 
+/// A query expression, and the part of the query text it was parsed from.
+#[derive(Clone, Debug)]
+pub struct Expr {
+    pub kind: ExprKind,
+
+    /// Where this expression appears in the query text, or `None` if it has no
+    /// position there, like an expression from the prelude.
+    pub span: Option<Span>,
+}
+
 #[derive(Clone, Debug, Eq, PartialEq)]
-pub enum Expr {
+pub enum ExprKind {
     Number(u64),
     String(String),
+    Bool(bool),
     #[allow(clippy::vec_box)]
     StreamLiteral(Vec<Box<Expr>>),
 
@@ -17,6 +29,32 @@ pub enum Expr {
         predicate: Box<Predicate>
     },
 
+    /// A predicate expression, `{ P, ... }`, which evaluates to a function
+    /// applying the predicate to its argument.
+    Predicate {
+        id: LambdaId,
+        predicate: Box<Predicate>,
+    },
+
+    /// A record literal, `{| name: EXPR, ... |}`.
+    Record(Vec<(String, Box<Expr>)>),
+
+    /// A field projection, `ARG .field`. The parser also produces ordinary
+    /// applications `ARG field`; where `field` is a well-known field name and
+    /// not the name of any variable in scope, static analysis rewrites them
+    /// into projections.
+    Project {
+        arg: Box<Expr>,
+        field: String,
+    },
+
+    /// An arithmetic expression, `LEFT op RIGHT`.
+    Arith {
+        op: ArithOp,
+        left: Box<Expr>,
+        right: Box<Expr>,
+    },
+
     Var(Var),
     App {
         arg: Box<Expr>,
@@ -33,37 +71,104 @@ pub enum Expr {
 pub enum Var {
     // Special names of built-in operators. For now, these are reserved words,
     // not globals.
+    Added,
+    Avg,
+    CensusDiff,
+    Count,
+    Dominated,
     Edges,
     First,
+    GroupBy,
+    Idom,
     Map,
+    Max,
+    Min,
     Nodes,
     Paths,
+    PathsTo,
+    Referrers,
+    Removed,
+    Retained,
     Root,
+    SortBy,
+    Sum,
+    Top,
+
+    // Reference to the result of an earlier query in an interactive session:
+    // `%N`, or just `%` for the most recent.
+    Output(Option<usize>),
 
     // Reference to a global or local variable.
     Lexical { id: UseId, name: String },
 }
 
+/// A top-level definition, `let NAME = VALUE;`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Definition {
+    pub name: String,
+    pub value: Box<Expr>,
+}
+
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub enum PredicateOp {
     Find,
     Filter,
     Until,
+    Through,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ArithOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum CompareOp {
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+/// A predicate, and the part of the query text it was parsed from.
+#[derive(Clone, Debug)]
+pub struct Predicate {
+    pub kind: PredicateKind,
+
+    /// Where this predicate appears in the query text, if anywhere, as for
+    /// `Expr::span`.
+    pub span: Option<Span>,
 }
 
 #[derive(Clone, Debug)]
-pub enum Predicate {
+pub enum PredicateKind {
     Expr(Expr),
     Field(String, Box<Predicate>),
     Ends(Box<Predicate>),
     Any(Box<Predicate>),
     All(Box<Predicate>),
     Regex(Box<regex::Regex>),
+    /// Matches numbers that compare as `op` directs with the given expression's
+    /// value: `< EXPR`, `>= EXPR`, and so on.
+    Compare(CompareOp, Box<Expr>),
+    /// Matches numbers at least as large as the first expression, if present,
+    /// and less than the second, if present: `LOW..HIGH`, `LOW..`, `..HIGH`.
+    Range(Option<Box<Expr>>, Option<Box<Expr>>),
     And(Vec<Predicate>),
     Or(Vec<Predicate>),
     Not(Box<Predicate>),
 }
 
+/// A range of byte offsets in a query's text, `start..end`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
 #[derive(Clone, Copy, Eq, Ord, PartialEq, PartialOrd, Hash)]
 pub struct LambdaId(pub usize);
 
@@ -89,12 +194,32 @@ impl fmt::Debug for UseId {
 impl fmt::Debug for Var {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> Result<(), fmt::Error> {
         let simple = match self {
+            Var::Added => "added",
+            Var::Avg => "avg",
+            Var::CensusDiff => "censusdiff",
+            Var::Count => "count",
+            Var::Dominated => "dominated",
             Var::Edges => "edges",
             Var::First => "first",
+            Var::GroupBy => "groupby",
+            Var::Idom => "idom",
             Var::Map => "map",
+            Var::Max => "max",
+            Var::Min => "min",
             Var::Nodes => "nodes",
             Var::Paths => "paths",
+            Var::PathsTo => "pathsto",
+            Var::Referrers => "referrers",
+            Var::Removed => "removed",
+            Var::Retained => "retained",
             Var::Root => "root",
+            Var::SortBy => "sortby",
+            Var::Sum => "sum",
+            Var::Top => "top",
+            Var::Output(None) => "%",
+            Var::Output(Some(n)) => {
+                return write!(fmt, "%{}", n);
+            }
             Var::Lexical { id, name } => {
                 return write!(fmt, "{:?}:{:?})", id, name);
             }
@@ -103,53 +228,182 @@ impl fmt::Debug for Var {
     }
 }
 
+impl Expr {
+    /// Return a boxed expression of the given kind, parsed from the text
+    /// `start..end`.
+    pub fn spanned(kind: ExprKind, start: usize, end: usize) -> Box<Expr> {
+        Box::new(Expr { kind, span: Some(Span { start, end }) })
+    }
+}
+
+impl From<ExprKind> for Expr {
+    fn from(kind: ExprKind) -> Expr {
+        Expr { kind, span: None }
+    }
+}
+
+// Expressions are equal if they have the same structure, regardless of where
+// they appear in the query text.
+impl PartialEq for Expr {
+    fn eq(&self, other: &Expr) -> bool {
+        self.kind == other.kind
+    }
+}
+
+impl Eq for Expr {}
+
+impl Predicate {
+    /// Return a predicate of the given kind, parsed from the text `start..end`.
+    pub fn spanned(kind: PredicateKind, start: usize, end: usize) -> Predicate {
+        Predicate { kind, span: Some(Span { start, end }) }
+    }
+}
+
+impl From<PredicateKind> for Predicate {
+    fn from(kind: PredicateKind) -> Predicate {
+        Predicate { kind, span: None }
+    }
+}
+
 impl PartialEq for Predicate {
     fn eq(&self, other: &Predicate) -> bool {
+        self.kind == other.kind
+    }
+}
+
+impl Eq for Predicate {}
+
+impl PartialEq for PredicateKind {
+    fn eq(&self, other: &PredicateKind) -> bool {
+        use PredicateKind::*;
         match (self, other) {
-            (Predicate::Expr(lhs), Predicate::Expr(rhs)) => lhs == rhs,
-            (Predicate::Field(lhsn, lhsp), Predicate::Field(rhsn, rhsp)) => {
-                lhsn == rhsn && lhsp == rhsp
-            }
-            (Predicate::Ends(lhs), Predicate::Ends(rhs)) => lhs == rhs,
-            (Predicate::Any(lhs), Predicate::Any(rhs)) => lhs == rhs,
-            (Predicate::All(lhs), Predicate::All(rhs)) => lhs == rhs,
-            (Predicate::Regex(lhs), Predicate::Regex(rhs)) => lhs.as_str() == rhs.as_str(),
-            (Predicate::And(lhs), Predicate::And(rhs)) => lhs == rhs,
-            (Predicate::Or(lhs), Predicate::Or(rhs)) => lhs == rhs,
-            (Predicate::Not(lhs), Predicate::Not(rhs)) => lhs == rhs,
+            (Expr(lhs), Expr(rhs)) => lhs == rhs,
+            (Field(lhsn, lhsp), Field(rhsn, rhsp)) => lhsn == rhsn && lhsp == rhsp,
+            (Ends(lhs), Ends(rhs)) => lhs == rhs,
+            (Any(lhs), Any(rhs)) => lhs == rhs,
+            (All(lhs), All(rhs)) => lhs == rhs,
+            (Regex(lhs), Regex(rhs)) => lhs.as_str() == rhs.as_str(),
+            (Compare(lop, lhs), Compare(rop, rhs)) => lop == rop && lhs == rhs,
+            (Range(llow, lhigh), Range(rlow, rhigh)) => llow == rlow && lhigh == rhigh,
+            (And(lhs), And(rhs)) => lhs == rhs,
+            (Or(lhs), Or(rhs)) => lhs == rhs,
+            (Not(lhs), Not(rhs)) => lhs == rhs,
             _ => false,
         }
     }
 }
 
-impl Eq for Predicate {}
+impl Eq for PredicateKind {}
+
+/// A function that builds an expression, given its left-hand operand. See the
+/// `Suffix` nonterminal in the grammar.
+pub type Builder = Box<dyn FnOnce(Box<Expr>) -> ExprKind>;
+
+/// Return an expression that evaluates `body` with `definitions` in scope.
+///
+/// A definition `let x = VALUE;` followed by `BODY` is equivalent to `VALUE
+/// (|x| BODY)`, so each definition can use those before it, but not itself or
+/// those after it.
+pub fn bind_definitions<I>(definitions: I, body: Box<Expr>) -> Box<Expr>
+where
+    I: IntoIterator<Item = Definition>,
+    I::IntoIter: DoubleEndedIterator,
+{
+    definitions.into_iter().rev().fold(body, |body, Definition { name, value }| {
+        Box::new(Expr::from(ExprKind::App {
+            arg: value,
+            fun: Box::new(Expr::from(ExprKind::Lambda {
+                id: LambdaId(0),
+                formals: vec![name],
+                body,
+            })),
+        }))
+    })
+}
+
+/// Forget the positions of all the expressions and predicates in `expr`.
+///
+/// Definitions kept in a `Library` outlive the text they were parsed from, so
+/// their positions would be meaningless in any later query's text. Without
+/// positions, errors in their code are reported at the point of use instead.
+pub fn erase_spans(expr: &mut Expr) {
+    struct Eraser;
+    impl<'e> WalkerMut<'e> for Eraser {
+        type Error = ();
+        fn walk_expr(&mut self, expr: &'e mut Expr) -> Result<(), ()> {
+            expr.span = None;
+            expr.walk_children_mut(self)
+        }
+        fn walk_predicate(&mut self, predicate: &'e mut Predicate) -> Result<(), ()> {
+            predicate.span = None;
+            predicate.walk_children_mut(self)
+        }
+    }
+    let _ = Eraser.walk_expr(expr);
+}
 
-pub type Builder = Box<dyn FnOnce(Box<Expr>) -> Box<Expr>>;
+/// Return `text` with comments replaced by spaces. A comment is a line whose
+/// first non-blank characters are `//`.
+pub fn blank_comments(text: &str) -> String {
+    text.split_inclusive('\n')
+        .map(|line| {
+            if line.trim_start().starts_with("//") {
+                line.bytes().map(|b| if b == b'\n' { '\n' } else { ' ' }).collect()
+            } else {
+                line.to_owned()
+            }
+        })
+        .collect()
+}
 
 // Given the text of a string literal, `literal`, return the `String` it
-// denotes.
-pub fn denoted_string(literal: &str) -> String {
+// denotes, or `None` if it ends with an incomplete escape.
+pub fn denoted_string(literal: &str) -> Option<String> {
     let mut result = String::with_capacity(literal.len());
     let mut iter = literal.chars();
     while let Some(ch) = iter.next() {
         match ch {
-            // String literals never end with a backslash.
-            '\\' => result.push(iter.next().unwrap()),
+            '\\' => result.push(iter.next()?),
             ch => result.push(ch),
         }
     }
-    result
+    Some(result)
 }
 
-pub fn denoted_regex(literal: &str) -> String {
+// Given the text of a regex literal, `literal`, return the regular expression
+// syntax it denotes, or `None` if it ends with an incomplete escape.
+pub fn denoted_regex(literal: &str) -> Option<String> {
     let mut result = String::with_capacity(literal.len());
     let mut iter = literal.chars();
     while let Some(ch) = iter.next() {
         match ch {
-            // Regex literals never end with a backslash.
-            '\\' => result.push(iter.next().unwrap()),
+            '\\' => result.push(iter.next()?),
             ch => result.push(ch),
         }
     }
-    result
+    Some(result)
+}
+
+/// Compile the regular expression `pattern`, applying `flags`, the letters
+/// following a regex literal, like the `i` in `/window/i`. On failure, return a
+/// brief description of the problem.
+pub fn build_regex(pattern: &str, flags: &str) -> Result<regex::Regex, String> {
+    if let Some(flag) = flags.chars().find(|&flag| !"imsxU".contains(flag)) {
+        return Err(format!("unknown flag '{}'", flag));
+    }
+
+    // Apply the flags inline, so that they show up in `Regex::as_str`.
+    let pattern = if flags.is_empty() {
+        pattern.to_owned()
+    } else {
+        format!("(?{}){}", flags, pattern)
+    };
+
+    regex::Regex::new(&pattern).map_err(|error| {
+        // The regex crate's syntax errors quote the pattern, with their own
+        // caret; just keep the description on the last line.
+        let message = error.to_string();
+        let last = message.lines().last().unwrap_or("");
+        last.strip_prefix("error: ").unwrap_or(last).to_owned()
+    })
 }
@@ -1,9 +1,36 @@
 //! Breadth-first traversal of the graph of `Node`s in a `CoreDump`.
 
 use crate::dump::{CoreDump, Edge, NodeId};
+use fallible_iterator::FallibleIterator;
+use super::value::Error;
 
+use std::cell::Cell;
 use std::collections::hash_map::Entry;
 use std::collections::{HashMap, VecDeque};
+use std::rc::Rc;
+
+/// What a traversal should do when it reaches an edge whose referent is not
+/// in the snapshot.
+#[derive(Clone, Debug, Default)]
+pub enum DanglingEdges {
+    /// Fail, with an error identifying the edge.
+    #[default]
+    Fail,
+
+    /// Ignore the edge, and add one to the given count.
+    Skip(Rc<Cell<usize>>),
+}
+
+impl DanglingEdges {
+    /// Return the number of edges skipped since the last call, and reset the
+    /// count.
+    pub fn take_skipped(&self) -> usize {
+        match self {
+            DanglingEdges::Fail => 0,
+            DanglingEdges::Skip(count) => count.take(),
+        }
+    }
+}
 
 #[derive(Clone, Debug)]
 pub struct BreadthFirst<'a> {
@@ -19,6 +46,9 @@ pub struct BreadthFirst<'a> {
     /// iteration items. Also known as 'grey nodes'. When this is empty, the
     /// traversal is over.
     front: VecDeque<NodeId>,
+
+    /// What to do about edges to nodes absent from `dump`.
+    dangling: DanglingEdges,
 }
 
 /// One step in a path: an `Edge` together with the node from which it
@@ -26,23 +56,29 @@ pub struct BreadthFirst<'a> {
 #[derive(Clone, Debug)]
 pub struct Step<'a> {
     pub origin: NodeId,
-    pub edge: &'a Edge<'a>,
+    pub edge: Edge<'a>,
 }
 
 impl<'a> BreadthFirst<'a> {
-    pub fn new(dump: &'a CoreDump<'a>) -> BreadthFirst<'a> {
+    pub fn new(dump: &'a CoreDump<'a>, dangling: DanglingEdges) -> BreadthFirst<'a> {
         BreadthFirst {
             dump,
             visited: HashMap::new(),
             front: VecDeque::new(),
+            dangling,
         }
     }
 
-    /// Add `node` to the set of nodes from which traversal should begin.
-    pub fn add_start_node(&mut self, node: NodeId) {
-        assert!(self.dump.has_node(node));
+    /// Add `node` to the set of nodes from which traversal should begin. It is
+    /// an error if `node` is not in the dump, as can happen with nodes from a
+    /// baseline snapshot.
+    pub fn add_start_node(&mut self, node: NodeId) -> Result<(), Error> {
+        if !self.dump.has_node(node) {
+            return Err(Error::NoSuchNode { id: node });
+        }
         self.visited.insert(node, None);
         self.front.push_back(node);
+        Ok(())
     }
 
     /// If `id` has been reached by the traversal so far (or is a start node),
@@ -69,8 +105,9 @@ impl<'a> BreadthFirst<'a> {
     }
 }
 
-impl<'a> Iterator for BreadthFirst<'a> {
+impl<'a> FallibleIterator for BreadthFirst<'a> {
     type Item = Vec<Step<'a>>;
+    type Error = Error;
 
     /// Return a shortest path by which one can reach the next node visited in
     /// breadth-first order, or `None` if there are no more nodes reachable from
@@ -85,14 +122,37 @@ impl<'a> Iterator for BreadthFirst<'a> {
     ///
     /// - The path from any produced node back to the (or a) start node is among
     ///   the shortest such paths in the entire `CoreDump`.
-    fn next(&mut self) -> Option<Vec<Step<'a>>> {
+    ///
+    /// - Among paths of the same length, the order depends only on the order
+    ///   in which start nodes were added and the order of each node's edges,
+    ///   never on hash table order, so it is the same from run to run.
+    ///
+    /// If we reach an edge whose referent is not in the dump, either fail or
+    /// skip it, as directed by the `DanglingEdges` policy.
+    fn next(&mut self) -> Result<Option<Vec<Step<'a>>>, Error> {
         if let Some(id) = self.front.pop_front() {
             // Look over this node's outgoing edges, and see if they reach any
             // new nodes. If so, record how we reached them, and queue them to
-            // be produced later.
-            let node = self.dump.get_node(id).unwrap();
-            for edge in &node.edges {
+            // be produced later. We only ever queue nodes present in the dump.
+            let node = self.dump.get_node(id).expect("queued node not in dump");
+            for edge in node.edges() {
                 if let Some(referent) = edge.referent {
+                    if !self.dump.has_node(referent) {
+                        match &self.dangling {
+                            DanglingEdges::Fail => {
+                                return Err(Error::DanglingEdge {
+                                    origin: id,
+                                    name: edge.name.map(|name| name.to_string()),
+                                    referent,
+                                });
+                            }
+                            DanglingEdges::Skip(count) => {
+                                count.set(count.get() + 1);
+                                continue;
+                            }
+                        }
+                    }
+
                     // Have we reached this edge's referent before?
                     match self.visited.entry(referent) {
                         // We have! Ignore this edge and its referent.
@@ -109,9 +169,47 @@ impl<'a> Iterator for BreadthFirst<'a> {
                 }
             }
 
-            Some(self.path_from_start(id).unwrap())
+            Ok(Some(self.path_from_start(id).unwrap()))
         } else {
-            None
+            Ok(None)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::dump::{CoreDump, NodeId};
+    use fallible_iterator::FallibleIterator;
+    use std::path::Path;
+    use super::{BreadthFirst, DanglingEdges};
+    use super::super::value::Error;
+
+    #[test]
+    fn dangling_edges() {
+        // Metadata, then a root node 1 with edges to 9, which is absent, and
+        // to 2.
+        let bytes = [
+            2, 0x08, 1,
+            10, 0x08, 1, 0x2a, 2, 0x08, 9, 0x2a, 2, 0x08, 2,
+            2, 0x08, 2,
+        ];
+        let dump = CoreDump::from_bytes(Path::new("dangling"), &bytes).expect("load failed");
+
+        let mut traversal = BreadthFirst::new(&dump, DanglingEdges::Fail);
+        traversal.add_start_node(NodeId(1)).unwrap();
+        match traversal.next() {
+            Err(Error::DanglingEdge { origin: NodeId(1), referent: NodeId(9), .. }) => (),
+            Err(other) => panic!("unexpected error: {}", other),
+            Ok(_) => panic!("traversal should have failed"),
         }
+
+        let skip = DanglingEdges::Skip(Default::default());
+        let mut traversal = BreadthFirst::new(&dump, skip.clone());
+        traversal.add_start_node(NodeId(1)).unwrap();
+        assert!(traversal.add_start_node(NodeId(9)).is_err());
+        let lengths: Vec<usize> = traversal.map(|path| Ok(path.len())).collect().unwrap();
+        assert_eq!(lengths, vec![0, 1]);
+        assert_eq!(skip.take_skipped(), 1);
+        assert_eq!(skip.take_skipped(), 0);
     }
 }
@@ -1,8 +1,8 @@
 use crate::id_vec::IdVec;
 use super::{Context, EvalResult, Plan, StaticError, Value};
-use super::ast::{Expr, LambdaId, UseId, Var};
+use super::ast::{Expr, ExprKind, LambdaId, UseId, Var};
 use super::run::plan_expr;
-use super::value::{Callable, Error, Function};
+use super::value::{Callable, Error, Function, BUILT_IN_FIELDS};
 use super::walkers::{Walkable, Walker, WalkerMut};
 
 use std::collections::{HashMap, HashSet};
@@ -222,11 +222,20 @@ impl ExprLabeler {
 impl<'e> WalkerMut<'e> for ExprLabeler {
     type Error = StaticError;
     fn walk_expr(&mut self, expr: &'e mut Expr) -> Result<(), StaticError> {
-        match expr {
-            Expr::Lambda { id, .. } | Expr::PredicateOp { id, .. } => {
+        match &mut expr.kind {
+            ExprKind::Lambda { id, .. } |
+            ExprKind::Predicate { id, .. } => {
                 *id = self.next_lambda();
             }
-            Expr::Var(Var::Lexical { id, .. }) => {
+            ExprKind::PredicateOp { id, stream, predicate, .. } => {
+                // The stream is outside the predicate's capture, so number any
+                // lambdas it contains first, matching the order in which
+                // `CaptureMapBuilder` visits them.
+                self.walk_expr(stream)?;
+                *id = self.next_lambda();
+                return self.walk_predicate(predicate);
+            }
+            ExprKind::Var(Var::Lexical { id, .. }) => {
                 *id = self.next_use();
             }
             _ => (),
@@ -235,6 +244,94 @@ impl<'e> WalkerMut<'e> for ExprLabeler {
     }
 }
 
+/// A `WalkerMut` that rewrites applications of field names not bound by any
+/// enclosing lambda, like `size` in `nodes size`, into field projections.
+///
+/// Only the names in `BUILT_IN_FIELDS` may be written without a `.`. Other
+/// fields, like those of record literals, need the `.`, so that a misspelled
+/// variable is reported as unbound, rather than becoming a projection that
+/// fails at run time.
+#[derive(Default)]
+struct FieldResolver {
+    /// The parameter lists of the lambdas enclosing this point in the
+    /// traversal.
+    scopes: Vec<Vec<String>>,
+}
+
+impl FieldResolver {
+    fn is_bound(&self, name: &str) -> bool {
+        self.scopes.iter().any(|formals| formals.iter().any(|f| f == name))
+    }
+}
+
+impl<'e> WalkerMut<'e> for FieldResolver {
+    type Error = StaticError;
+    fn walk_expr(&mut self, expr: &'e mut Expr) -> Result<(), StaticError> {
+        let field = match &expr.kind {
+            ExprKind::Lambda { formals, .. } => {
+                self.scopes.push(formals.clone());
+                expr.walk_children_mut(self)?;
+                self.scopes.pop();
+                return Ok(());
+            }
+            ExprKind::App { fun, .. } => match &fun.kind {
+                ExprKind::Var(Var::Lexical { name, .. })
+                    if BUILT_IN_FIELDS.contains(&name.as_str()) && !self.is_bound(name) =>
+                {
+                    Some(name.clone())
+                }
+                _ => None,
+            },
+            _ => None,
+        };
+
+        if let Some(field) = field {
+            if let ExprKind::App { arg, .. } = replace(&mut expr.kind, ExprKind::Bool(false)) {
+                expr.kind = ExprKind::Project { arg, field };
+            }
+        }
+        expr.walk_children_mut(self)
+    }
+}
+
+/// A `Walker` that rejects uses of built-in operators like `sum` or `edges`
+/// other than direct applications, like `nodes size sum`. The planner
+/// implements those operators only for applications, so they can't yet be
+/// bound to variables or passed to other functions.
+struct OperatorChecker;
+
+impl OperatorChecker {
+    fn is_operator(var: &Var) -> bool {
+        matches!(var,
+                 Var::Avg | Var::CensusDiff | Var::Count | Var::Dominated | Var::Edges |
+                 Var::First | Var::Idom | Var::Max | Var::Min | Var::Paths | Var::PathsTo |
+                 Var::Referrers | Var::Sum)
+    }
+}
+
+impl<'e> Walker<'e> for OperatorChecker {
+    type Error = StaticError;
+
+    fn walk_expr(&mut self, expr: &'e Expr) -> Result<(), StaticError> {
+        match &expr.kind {
+            ExprKind::Var(var) if Self::is_operator(var) => Err(StaticError::Operator {
+                name: format!("{:?}", var),
+                span: expr.span,
+            }),
+            ExprKind::App { arg, fun } => match &fun.kind {
+                ExprKind::Var(var) if Self::is_operator(var) => self.walk_expr(arg),
+                // `NODE (LIMIT pathsto)`
+                ExprKind::App { arg: limit, fun: inner } if inner.kind == ExprKind::Var(Var::PathsTo) => {
+                    self.walk_expr(limit)?;
+                    self.walk_expr(arg)
+                }
+                _ => expr.walk_children(self),
+            },
+            _ => expr.walk_children(self),
+        }
+    }
+}
+
 /// An identifier for a lexical variable.
 ///
 /// A value `VarNum { lambda, index }` refers to the `index`'th formal parameter
@@ -330,24 +427,25 @@ impl<'e> Walker<'e> for CaptureMapBuilder<'e> {
 
     fn walk_expr(&mut self, expr: &'e Expr) -> Result<(), StaticError> {
         let enclosing = self.scopes.last().map(|(id, _)| *id);
-        match expr {
-            Expr::Var(Var::Lexical { id, ref name }) => {
+        match &expr.kind {
+            ExprKind::Var(Var::Lexical { id, name }) => {
                 if let Some(referent) = self.find_var(name) {
                     self.map.uses.push_at(*id, UseInfo { lambda: enclosing, referent });
                     self.captured.insert(referent);
                 } else {
                     return Err(StaticError::UnboundVar {
                         name: name.to_owned(),
+                        span: expr.span,
                     });
                 }
                 Ok(())
             }
-            Expr::Lambda { id, formals, .. } => {
+            ExprKind::Lambda { id, formals, .. } => {
                 self.with_capture(*id, formals, enclosing, |builder| {
                     expr.walk_children(builder)
                 })
             }
-            Expr::PredicateOp { id, stream, predicate, .. } => {
+            ExprKind::PredicateOp { id, stream, predicate, .. } => {
                 // The stream falls outside the capture, but the predicate runs
                 // while the stream is being consumed, so it needs to be inside
                 // the capture.
@@ -356,7 +454,12 @@ impl<'e> Walker<'e> for CaptureMapBuilder<'e> {
                     builder.walk_predicate(predicate)
                 })
             }
-            other => other.walk_children(self),
+            ExprKind::Predicate { id, predicate } => {
+                self.with_capture(*id, &[], enclosing, |builder| {
+                    builder.walk_predicate(predicate)
+                })
+            }
+            _ => expr.walk_children(self),
         }
     }
 }
@@ -506,9 +609,14 @@ pub struct StaticAnalysis(ClosureLayouts);
 
 impl StaticAnalysis {
     pub fn from_expr(expr: &mut Expr) -> Result<StaticAnalysis, StaticError> {
+        // Turn applications of unbound names into field projections.
+        FieldResolver::default().walk_expr(expr)?;
+
+        // Make sure built-in operators are only applied, never used as values.
+        OperatorChecker.walk_expr(expr)?;
+
         // Label lambdas, variable uses, etc.
         ExprLabeler::new().walk_expr(expr)?;
-        eprintln!("labeled expr: {:?}", expr);
 
         // Build a map of which variables are captured by which lambdas.
         let map = {
@@ -516,7 +624,6 @@ impl StaticAnalysis {
             builder.walk_expr(expr)?;
             builder.build()
         };
-        eprintln!("{:#?}", map);
 
         // Chose how each lambda's closure should be laid out, and then note the
         // location each variable reference now refers to.
@@ -601,9 +708,9 @@ impl Plan for Call {
 
 #[cfg(test)]
 mod test {
-    use super::{CaptureMap, CaptureMapBuilder, LambdaInfo, UseInfo, VarAddr};
-    use crate::query::ast::{Expr, LambdaId};
-    use crate::query::walkers::Walker;
+    use super::{CaptureMap, CaptureMapBuilder, ExprLabeler, FieldResolver, LambdaInfo, UseInfo, VarAddr};
+    use crate::query::ast::{Expr, ExprKind, LambdaId};
+    use crate::query::walkers::{Walker, WalkerMut};
     use crate::query::test_utils::*;
     use crate::id_vec::IdVec;
     use std::collections::HashSet;
@@ -631,6 +738,23 @@ mod test {
         assert!(cm.uses.is_empty());
     }
 
+    #[test]
+    fn field_projection() {
+        // |x| x size: `size` is unbound, so this is a projection.
+        let mut expr = lambda(0, &["x"], app(var(0, "x"), var(1, "size")));
+        FieldResolver::default().walk_expr(&mut expr).expect("resolve fields");
+        assert_eq!(
+            expr,
+            lambda(0, &["x"], Box::new(Expr::from(ExprKind::Project { arg: var(0, "x"), field: "size".to_owned() })))
+        );
+
+        // |size| 3 size: `size` is bound, so this is an ordinary application.
+        let mut expr = lambda(0, &["size"], app(number(3), var(0, "size")));
+        let expected = expr.clone();
+        FieldResolver::default().walk_expr(&mut expr).expect("resolve fields");
+        assert_eq!(expr, expected);
+    }
+
     #[test]
     fn single_lambda() {
         let expr = lambda(0, &["x", "y", "z"], app(var(0, "y"), var(1, "z")));
@@ -720,5 +844,21 @@ mod test {
             ])
         );
     }
-}
 
+    #[test]
+    fn lambda_in_filtered_stream() {
+        // nodes (|n| n) { 7 }: the lambda in the filter's stream is numbered
+        // before the filter's predicate, in both the labeler and the capture map.
+        let mut expr = filter(app(nodes(), lambda(0, &["n"], var(0, "n"))),
+                              and1(expr_pred(number(7))));
+        ExprLabeler::new().walk_expr(&mut expr).expect("label expression");
+        let cm = make_capture_map(&expr);
+        assert_eq!(
+            cm.lambdas,
+            IdVec::from_iter(vec![
+                LambdaInfo { arity: 1, parent: None, captured: HashSet::new() },
+                LambdaInfo { arity: 0, parent: None, captured: HashSet::new() },
+            ])
+        );
+    }
+}
@@ -0,0 +1,297 @@
+//! Writing query results as JSON.
+//!
+//! Nodes, edges, and frames are written as objects with the same field names
+//! that queries use, omitting absent optional fields. A node's
+//! `allocationStack` and a frame's `parent` are written as frame ids, rather
+//! than repeating the entire stack. Streams are written as arrays, producing
+//! elements as they go, so even very long streams need not fit in memory.
+
+use anyhow::bail;
+use crate::dump::{Edge, Frame, Node};
+use fallible_iterator::FallibleIterator;
+use std::fmt::Display;
+use std::io;
+use super::value::{Record, Value};
+
+/// Write `value` to `output` as a single JSON value. If `value` is a stream,
+/// put each element on a line of its own.
+pub fn write_json(value: &Value, output: &mut dyn io::Write) -> Result<(), anyhow::Error> {
+    match value {
+        Value::Stream(stream) => {
+            let mut stream = stream.clone();
+            write!(output, "[")?;
+            let mut first = true;
+            while let Some(value) = stream.next()? {
+                if !first {
+                    write!(output, ",")?;
+                }
+                writeln!(output)?;
+                write_value(&value, output)?;
+                first = false;
+            }
+            write!(output, "\n]")?;
+        }
+        _ => write_value(value, output)?,
+    }
+    writeln!(output)?;
+    Ok(())
+}
+
+/// Write `value` to `output` in JSON Lines format: if `value` is a stream, write
+/// each element as a JSON value on a line of its own; otherwise, write `value`
+/// alone on a line.
+pub fn write_json_lines(value: &Value, output: &mut dyn io::Write) -> Result<(), anyhow::Error> {
+    match value {
+        Value::Stream(stream) => {
+            let mut stream = stream.clone();
+            while let Some(value) = stream.next()? {
+                write_value(&value, output)?;
+                writeln!(output)?;
+            }
+        }
+        _ => {
+            write_value(value, output)?;
+            writeln!(output)?;
+        }
+    }
+    Ok(())
+}
+
+/// Write `value` to `output` as JSON, on a single line.
+fn write_value(value: &Value, output: &mut dyn io::Write) -> Result<(), anyhow::Error> {
+    match value {
+        Value::Number(n) => write!(output, "{}", n)?,
+        Value::String(s) => write_string(s, output)?,
+        Value::Bool(b) => write!(output, "{}", b)?,
+        Value::Edge(edge) => write_edge(edge, output)?,
+        Value::Node(node) => write_node(node, output)?,
+        Value::Frame(frame) => write_frame(frame, output)?,
+        Value::Record(record) => write_record(record, output)?,
+        Value::Function(f) => bail!("can't write function {:?} as JSON", f.0.name()),
+        Value::Stream(stream) => {
+            let mut stream = stream.clone();
+            write!(output, "[")?;
+            let mut first = true;
+            while let Some(value) = stream.next()? {
+                if !first {
+                    write!(output, ",")?;
+                }
+                write_value(&value, output)?;
+                first = false;
+            }
+            write!(output, "]")?;
+        }
+    }
+    Ok(())
+}
+
+fn write_node(node: &Node, output: &mut dyn io::Write) -> Result<(), anyhow::Error> {
+    let mut object = ObjectWriter::new(output)?;
+    object.literal("id", Some(node.id().0))?;
+    object.string("coarseType", Some(String::from(node.coarseType())))?;
+    object.string("typeName", node.typeName())?;
+    object.literal("size", node.size())?;
+    object.string("JSObjectClassName", node.JSObjectClassName())?;
+    object.string("scriptFilename", node.scriptFilename())?;
+    object.string("descriptiveTypeName", node.descriptiveTypeName())?;
+    object.literal("allocationStack", node.allocationStack().map(|id| id.0))?;
+
+    let output = object.member("edges")?;
+    write!(output, "[")?;
+    for (i, edge) in node.edges().enumerate() {
+        if i > 0 {
+            write!(output, ",")?;
+        }
+        write_edge(&edge, output)?;
+    }
+    write!(output, "]")?;
+
+    object.finish()
+}
+
+fn write_edge(edge: &Edge, output: &mut dyn io::Write) -> Result<(), anyhow::Error> {
+    let mut object = ObjectWriter::new(output)?;
+    object.string("name", edge.name)?;
+    object.literal("referent", edge.referent.map(|id| id.0))?;
+    object.finish()
+}
+
+fn write_frame(frame: &Frame, output: &mut dyn io::Write) -> Result<(), anyhow::Error> {
+    let mut object = ObjectWriter::new(output)?;
+    object.literal("id", Some(frame.id.0))?;
+    object.string("functionDisplayName", frame.functionDisplayName)?;
+    object.string("source", frame.source)?;
+    object.literal("line", frame.line)?;
+    object.literal("column", frame.column)?;
+    object.literal("isSystem", frame.isSystem)?;
+    object.literal("isSelfHosted", frame.isSelfHosted)?;
+    object.literal("parent", frame.parent.map(|id| id.0))?;
+    object.finish()
+}
+
+fn write_record(record: &Record, output: &mut dyn io::Write) -> Result<(), anyhow::Error> {
+    let mut object = ObjectWriter::new(output)?;
+    for (name, value) in record.fields() {
+        write_value(value, object.member(name)?)?;
+    }
+    object.finish()
+}
+
+/// Write `s` to `output` as a JSON string literal.
+fn write_string(s: &str, output: &mut dyn io::Write) -> io::Result<()> {
+    write!(output, "\"")?;
+    // Write unescaped runs of characters all at once.
+    let mut start = 0;
+    for (i, ch) in s.char_indices() {
+        let escaped = match ch {
+            '"' => "\\\"",
+            '\\' => "\\\\",
+            '\n' => "\\n",
+            '\r' => "\\r",
+            '\t' => "\\t",
+            ch if ch < ' ' => "",
+            _ => continue,
+        };
+        output.write_all(&s.as_bytes()[start..i])?;
+        if escaped.is_empty() {
+            write!(output, "\\u{:04x}", ch as u32)?;
+        } else {
+            output.write_all(escaped.as_bytes())?;
+        }
+        start = i + ch.len_utf8();
+    }
+    output.write_all(&s.as_bytes()[start..])?;
+    write!(output, "\"")
+}
+
+/// A helper for writing the members of a JSON object, one by one.
+struct ObjectWriter<'o> {
+    output: &'o mut dyn io::Write,
+    first: bool,
+}
+
+impl<'o> ObjectWriter<'o> {
+    fn new(output: &'o mut dyn io::Write) -> io::Result<ObjectWriter<'o>> {
+        write!(output, "{{")?;
+        Ok(ObjectWriter { output, first: true })
+    }
+
+    /// Begin a member named `name`, and return the stream to which its value
+    /// should be written.
+    fn member(&mut self, name: &str) -> io::Result<&mut dyn io::Write> {
+        if !self.first {
+            write!(self.output, ",")?;
+        }
+        self.first = false;
+        write_string(name, self.output)?;
+        write!(self.output, ":")?;
+        Ok(&mut *self.output)
+    }
+
+    /// Write a member whose value is a string, if `value` is present.
+    fn string<T: Display>(&mut self, name: &str, value: Option<T>) -> io::Result<()> {
+        match value {
+            Some(value) => write_string(&value.to_string(), self.member(name)?),
+            None => Ok(()),
+        }
+    }
+
+    /// Write a member whose value is a number or boolean, if `value` is present.
+    fn literal<T: Display>(&mut self, name: &str, value: Option<T>) -> io::Result<()> {
+        match value {
+            Some(value) => write!(self.member(name)?, "{}", value),
+            None => Ok(()),
+        }
+    }
+
+    fn finish(self) -> Result<(), anyhow::Error> {
+        write!(self.output, "}}")?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::dump::CoreDump;
+    use crate::dump::test_utils::{allocation_stack, delimited, frame, node, snapshot};
+    use crate::query::Format;
+    use crate::query::stream::Stream;
+    use crate::query::test_utils::eval_formatted;
+    use crate::query::value::{Record, Value};
+    use std::path::Path;
+    use std::rc::Rc;
+    use super::{write_json, write_json_lines, write_string};
+
+    fn to_json(value: &Value) -> String {
+        let mut output = Vec::new();
+        write_json(value, &mut output).expect("write_json failed");
+        String::from_utf8(output).unwrap()
+    }
+
+    fn to_json_lines(value: &Value) -> String {
+        let mut output = Vec::new();
+        write_json_lines(value, &mut output).expect("write_json_lines failed");
+        String::from_utf8(output).unwrap()
+    }
+
+    fn stream<'a>(values: Vec<Value<'a>>) -> Value<'a> {
+        let iter = fallible_iterator::convert(values.into_iter().map(Ok));
+        Value::from(Stream::new(iter))
+    }
+
+    #[test]
+    fn strings() {
+        let mut output = Vec::new();
+        write_string("plain \"quoted\" back\\slash\nnewline \u{1} \u{e9}", &mut output).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(),
+                   r#""plain \"quoted\" back\\slash\nnewline \u0001 é""#);
+    }
+
+    #[test]
+    fn values() {
+        let record = Value::Record(Rc::new(Record::new(vec![
+            ("key".to_owned(), Value::from("Window".to_owned())),
+            ("count".to_owned(), Value::from(3_u64)),
+            ("path".to_owned(), stream(vec![Value::from(true), stream(vec![])])),
+        ])));
+        assert_eq!(to_json(&record),
+                   "{\"key\":\"Window\",\"count\":3,\"path\":[true,[]]}\n");
+        assert_eq!(to_json(&stream(vec![Value::from(1_u64), record.clone()])),
+                   "[\n1,\n{\"key\":\"Window\",\"count\":3,\"path\":[true,[]]}\n]\n");
+        assert_eq!(to_json_lines(&stream(vec![Value::from(1_u64), record])),
+                   "1\n{\"key\":\"Window\",\"count\":3,\"path\":[true,[]]}\n");
+        assert_eq!(to_json_lines(&stream(vec![])), "");
+        assert_eq!(to_json_lines(&Value::from(7_u64)), "7\n");
+    }
+
+    #[test]
+    fn snapshot_values() {
+        let mut window = delimited(7, b"Window");
+        window.extend(allocation_stack(10, "f", Some(&frame(11, "g", None))));
+        let bytes = snapshot(&[node(1, 8, &[(2, "child")], &[]), node(2, 16, &[], &window)]);
+        let dump = CoreDump::from_bytes(Path::new("json"), &bytes).unwrap();
+        let json: Format = "json".parse().unwrap();
+        let jsonl: Format = "jsonl".parse().unwrap();
+        let eval = |query: &str, format| eval_formatted(&dump, None, query, format).unwrap();
+
+        // Absent optional fields are omitted; stacks are written as frame ids.
+        assert_eq!(eval("nodes (\"id\" sortby)", &jsonl),
+                   "{\"id\":1,\"coarseType\":\"Other\",\"size\":8,\"edges\":[{\"name\":\"child\",\"referent\":2}]}\n\
+                    {\"id\":2,\"coarseType\":\"Other\",\"size\":16,\"JSObjectClassName\":\"Window\",\
+                    \"allocationStack\":10,\"edges\":[]}\n");
+        assert_eq!(eval("root edges", &json), "[\n{\"name\":\"child\",\"referent\":2}\n]\n");
+        assert_eq!(eval("nodes { id: 2 } first allocationStack", &jsonl),
+                   "{\"id\":10,\"functionDisplayName\":\"f\",\"parent\":11}\n\
+                    {\"id\":11,\"functionDisplayName\":\"g\"}\n");
+
+        // Nested streams are written as nested arrays, each on one line.
+        assert_eq!(eval("root paths", &json),
+                   "[\n\
+                    [{\"id\":1,\"coarseType\":\"Other\",\"size\":8,\"edges\":[{\"name\":\"child\",\"referent\":2}]},\
+                    {\"name\":\"child\",\"referent\":2},\
+                    {\"id\":2,\"coarseType\":\"Other\",\"size\":16,\"JSObjectClassName\":\"Window\",\
+                    \"allocationStack\":10,\"edges\":[]}]\n\
+                    ]\n");
+        assert_eq!(eval("[[1, 2], [], [[3]]]", &jsonl), "[1,2]\n[]\n[[3]]\n");
+    }
+}
@@ -0,0 +1,178 @@
+//! Breaking query text into tokens.
+//!
+//! LALRPOP's built-in lexer can't see context, so it has no way to tell the
+//! regular expression `/2) + (6/` from the two divisions in `(8/2) + (6/3)`.
+//! This lexer decides by looking at the preceding token: after something that
+//! can end an operand, `/` is division; anywhere else, it begins a regular
+//! expression literal.
+
+use super::ast::Span;
+use super::StaticError;
+use std::fmt;
+
+/// A token of query text, holding the text it was lexed from.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Token<'input> {
+    /// A keyword or punctuation, like `let` or `{|`.
+    Fixed(&'input str),
+    Ident(&'input str),
+    Hex(&'input str),
+    Decimal(&'input str),
+    Str(&'input str),
+    /// A reference to an earlier query's result, like `%3`.
+    Output(&'input str),
+    Regex(&'input str),
+    RawRegex(&'input str),
+}
+
+/// Reserved words. Those in `PREFIX_KEYWORDS` expect an operand to follow;
+/// the rest are operands themselves.
+const KEYWORDS: &[&str] = &[
+    "added", "all", "and", "any", "avg", "censusdiff", "count", "dominated",
+    "edges", "ends", "false", "find", "first", "groupby", "idom", "let", "map",
+    "max", "min", "nodes", "not", "or", "paths", "pathsto", "referrers",
+    "removed", "retained", "root", "sortby", "sum", "through", "top", "true",
+    "until",
+];
+
+const PREFIX_KEYWORDS: &[&str] = &[
+    "all", "and", "any", "ends", "find", "let", "not", "or", "through", "until",
+];
+
+/// Punctuation, with each token listed before any proper prefix of it.
+const PUNCTUATION: &[&str] = &[
+    "{|", "|}", "..", "<=", ">=",
+    "(", ")", "[", "]", "{", "}", "|", ",", ":", ";", "=", ".", "+", "-", "*", "/", "<", ">",
+];
+
+impl<'input> Token<'input> {
+    fn text(&self) -> &'input str {
+        match *self {
+            Token::Fixed(text) |
+            Token::Ident(text) |
+            Token::Hex(text) |
+            Token::Decimal(text) |
+            Token::Str(text) |
+            Token::Output(text) |
+            Token::Regex(text) |
+            Token::RawRegex(text) => text,
+        }
+    }
+
+    /// Return true if this token can be the last token of an operand, so that
+    /// a `/` following it must be division.
+    fn ends_operand(&self) -> bool {
+        match *self {
+            Token::Fixed(text) => match text {
+                ")" | "]" | "}" | "|}" => true,
+                _ => KEYWORDS.contains(&text) && !PREFIX_KEYWORDS.contains(&text),
+            },
+            _ => true,
+        }
+    }
+}
+
+impl fmt::Display for Token<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.text())
+    }
+}
+
+/// An iterator over the tokens of a query, in the form LALRPOP's generated
+/// parsers expect.
+pub struct Lexer<'input> {
+    text: &'input str,
+    pos: usize,
+    after_operand: bool,
+}
+
+impl<'input> Lexer<'input> {
+    pub fn new(text: &'input str) -> Lexer<'input> {
+        Lexer { text, pos: 0, after_operand: false }
+    }
+
+    /// Recognize the token at the start of `rest`, returning it and its
+    /// length, or an error message.
+    fn token(&self, rest: &'input str) -> Result<(Token<'input>, usize), &'static str> {
+        let bytes = rest.as_bytes();
+        let run = |from: usize, pred: fn(&u8) -> bool| {
+            from + bytes[from..].iter().take_while(|&b| pred(b)).count()
+        };
+        let lexed = |token: fn(&'input str) -> Token<'input>, len: usize| (token(&rest[..len]), len);
+
+        Ok(match bytes[0] {
+            b'a'..=b'z' | b'A'..=b'Z' | b'_' => {
+                let end = run(1, |b| b.is_ascii_alphanumeric() || *b == b'_');
+                if KEYWORDS.contains(&&rest[..end]) {
+                    lexed(Token::Fixed, end)
+                } else {
+                    lexed(Token::Ident, end)
+                }
+            }
+            b'0' if bytes.get(1) == Some(&b'x') && bytes.get(2).is_some_and(u8::is_ascii_hexdigit) => {
+                lexed(Token::Hex, run(2, u8::is_ascii_hexdigit))
+            }
+            b'0'..=b'9' => lexed(Token::Decimal, run(1, u8::is_ascii_digit)),
+            b'"' => lexed(Token::Str, delimited(rest, 1, "\"").ok_or("unterminated string literal")?),
+            b'%' => lexed(Token::Output, run(1, u8::is_ascii_digit)),
+            b'#' if bytes.get(1) == Some(&b'/') => {
+                let end = rest[2..].find("/#").ok_or("unterminated regex literal")? + 4;
+                lexed(Token::RawRegex, run(end, u8::is_ascii_alphabetic))
+            }
+            b'/' if !self.after_operand => {
+                let end = delimited(rest, 1, "/").ok_or("unterminated regex literal")?;
+                lexed(Token::Regex, run(end, u8::is_ascii_alphabetic))
+            }
+            _ => {
+                let punct = PUNCTUATION.iter().find(|p| rest.starts_with(*p)).ok_or("unexpected character")?;
+                lexed(Token::Fixed, punct.len())
+            }
+        })
+    }
+}
+
+/// Return the length of the prefix of `text` that ends with the first
+/// occurrence of `close` at or after `start` that isn't escaped by a
+/// backslash, if there is one.
+fn delimited(text: &str, start: usize, close: &str) -> Option<usize> {
+    let mut chars = text[start..].char_indices();
+    while let Some((i, ch)) = chars.next() {
+        if ch == '\\' {
+            chars.next();
+        } else if text[start + i..].starts_with(close) {
+            return Some(start + i + close.len());
+        }
+    }
+    None
+}
+
+impl<'input> Iterator for Lexer<'input> {
+    type Item = Result<(usize, Token<'input>, usize), StaticError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let rest = &self.text[self.pos..];
+        let trimmed = rest.trim_start();
+        self.pos += rest.len() - trimmed.len();
+        if trimmed.is_empty() {
+            return None;
+        }
+
+        let start = self.pos;
+        match self.token(trimmed) {
+            Ok((token, len)) => {
+                self.pos += len;
+                self.after_operand = token.ends_operand();
+                Some(Ok((start, token, self.pos)))
+            }
+            Err(message) => {
+                // Stop here; the parser won't ask for more after an error.
+                self.pos = self.text.len();
+                let end = start + trimmed.chars().next().map_or(0, char::len_utf8);
+                Some(Err(StaticError::Parse {
+                    message: message.to_owned(),
+                    span: Some(Span { start, end }),
+                }))
+            }
+        }
+    }
+}
@@ -2,6 +2,9 @@ mod ast;
 mod breadth_first;
 mod stream;
 mod fun;
+mod json;
+mod lexer;
+mod retaining;
 mod run;
 mod value;
 mod walkers;
@@ -15,15 +18,19 @@ mod grammar {
     include!(concat!(env!("OUT_DIR"), "/query/query.rs"));
 }
 
+pub use self::breadth_first::DanglingEdges;
 pub use self::fun::{Activation, ActivationBase, StaticAnalysis};
-pub use self::grammar::Token;
-pub use self::value::{EvalResult, Value};
+pub(crate) use self::lexer::{Lexer, Token};
+pub use self::value::{EvalResult, Format, Value};
 
+use anyhow::anyhow;
 use thiserror::Error;
 use crate::dump::CoreDump;
-use self::grammar::QueryParser;
+use self::ast::{bind_definitions, blank_comments, erase_spans, Definition, Expr, ExprKind, Span, Var};
+use self::grammar::{DefinitionsParser, QueryParser};
 use self::run::plan_expr;
 use std::fmt;
+use std::rc::Rc;
 
 /// A plan of evaluation. We translate each query expression into a tree of
 /// `Plan` values, which serve as the code for a sort of indirect-threaded
@@ -34,7 +41,7 @@ pub trait Plan: fmt::Debug {
     ///
     /// Use `Context::from_dump` to construct an initial `Context`.
     /// `Activation::for_eval` constructs an `Activation` appropriate for
-    /// running plans returned by `compile`.
+    /// running plans returned by `Library::compile`.
     fn run<'a, 'd>(&self, act: &'a Activation<'a, 'd>, cx: &Context<'d>) -> EvalResult<'d>;
 }
 
@@ -51,36 +58,222 @@ pub trait PredicatePlan: fmt::Debug {
 pub struct Context<'a> {
     /// The heap snapshot that operators like `nodes` and `root` should consult.
     pub dump: &'a CoreDump<'a>,
+
+    /// When comparing two snapshots, the earlier one, which operators like
+    /// `added` and `removed` compare `dump` against.
+    pub baseline: Option<&'a CoreDump<'a>>,
+
+    /// In an interactive session, the results of earlier queries, which `%N`
+    /// refers to. The first query's result is `%1`.
+    pub outputs: Rc<[Value<'a>]>,
+
+    /// What traversals like `paths` should do about edges whose referents
+    /// aren't in `dump`.
+    pub dangling_edges: DanglingEdges,
 }
 
 impl<'a> Context<'a> {
     pub fn from_dump(dump: &'a CoreDump<'a>) -> Context<'a> {
-        Context { dump }
+        Context { dump, baseline: None, outputs: Rc::new([]), dangling_edges: DanglingEdges::Fail }
+    }
+
+    /// Return a context for comparing `dump` against the earlier snapshot
+    /// `baseline`.
+    pub fn with_baseline(dump: &'a CoreDump<'a>, baseline: &'a CoreDump<'a>) -> Context<'a> {
+        Context {
+            dump,
+            baseline: Some(baseline),
+            outputs: Rc::new([]),
+            dangling_edges: DanglingEdges::Fail,
+        }
+    }
+
+    /// Return a copy of this context in which `%N` refers to `outputs`.
+    pub fn with_outputs(&self, outputs: &[Value<'a>]) -> Context<'a> {
+        Context { outputs: outputs.into(), ..self.clone() }
+    }
+
+    /// Return the earlier result that `%N` refers to, given `Some(N)`, or the
+    /// latest result, for `%`, given `None`.
+    pub fn output(&self, number: Option<usize>) -> Result<Value<'a>, value::Error> {
+        let output = match number {
+            Some(n) => n.checked_sub(1).and_then(|i| self.outputs.get(i)),
+            None => self.outputs.last(),
+        };
+        output.cloned().ok_or_else(|| value::Error::NoOutput {
+            name: format!("{:?}", Var::Output(number)),
+        })
+    }
+
+    /// Return a copy of this context in which traversals treat edges to
+    /// absent nodes according to `dangling_edges`.
+    pub fn with_dangling_edges(&self, dangling_edges: DanglingEdges) -> Context<'a> {
+        Context { dangling_edges, ..self.clone() }
     }
 }
 
-pub fn compile(query_text: &str) -> Result<Box<dyn Plan>, StaticError> {
-    let mut expr = QueryParser::new().parse(&query_text)?;
-    let analysis = StaticAnalysis::from_expr(&mut expr)?;
-    let plan = plan_expr(&expr, &analysis);
-    eprintln!("plan: {:#?}", plan);
-    Ok(plan)
+/// The standard definitions available to every query.
+const PRELUDE: &str = include_str!("prelude.fxq");
+
+/// A collection of top-level definitions that queries can use, from the prelude
+/// or from files of definitions.
+#[derive(Clone, Default)]
+pub struct Library {
+    definitions: Vec<Definition>,
 }
 
-pub type ParseError<'input> = lalrpop_util::ParseError<usize, Token<'input>, &'static str>;
+impl Library {
+    /// Return a library containing the definitions from the prelude.
+    pub fn with_prelude() -> Library {
+        let mut library = Library::default();
+        library.define(PRELUDE).expect("failed to parse prelude");
+        library
+    }
+
+    /// Add the definitions in `text` to this library. `text` should be a series
+    /// of definitions of the form `let NAME = EXPR;`. These can refer to
+    /// definitions added earlier, and can shadow them. If any definition is
+    /// erroneous, add none of them.
+    pub fn define(&mut self, text: &str) -> Result<(), StaticError> {
+        let mut definitions = DefinitionsParser::new().parse(Lexer::new(&blank_comments(text)))?;
+
+        // Analyze the new definitions in the scope of the old ones now, while
+        // they still have positions to report errors at. Otherwise, every
+        // later query would fail to compile, for reasons it can't point at.
+        let body = Box::new(Expr::from(ExprKind::Var(Var::Root)));
+        let mut expr = bind_definitions(self.definitions.iter().chain(&definitions).cloned(), body);
+        StaticAnalysis::from_expr(&mut expr)?;
+
+        for definition in &mut definitions {
+            erase_spans(&mut definition.value);
+        }
+        self.definitions.extend(definitions);
+        Ok(())
+    }
+
+    /// Compile `query_text`, which may use the definitions in this library, and
+    /// may begin with definitions of its own.
+    pub fn compile(&self, query_text: &str) -> Result<Box<dyn Plan>, StaticError> {
+        let expr = QueryParser::new().parse(Lexer::new(&blank_comments(query_text)))?;
+        let mut expr = bind_definitions(self.definitions.iter().cloned(), expr);
+        let analysis = StaticAnalysis::from_expr(&mut expr)?;
+        Ok(plan_expr(&expr, &analysis))
+    }
+}
+
+pub type ParseError<'input> = lalrpop_util::ParseError<usize, Token<'input>, StaticError>;
 
 /// An error raised during query planning.
 #[derive(Clone, Error, Debug)]
 pub enum StaticError {
-    #[error("error parsing query: {0}")]
-    Parse(String),
+    #[error("error parsing query: {message}")]
+    Parse { message: String, span: Option<Span> },
+
+    #[error("invalid regular expression: {message}")]
+    Regex { message: String, span: Option<Span> },
 
     #[error("unbound variable '{name}'")]
-    UnboundVar { name: String },
+    UnboundVar { name: String, span: Option<Span> },
+
+    #[error("'{name}' can only be applied directly to its argument, as in 'nodes size {name}'")]
+    Operator { name: String, span: Option<Span> },
+}
+
+impl StaticError {
+    /// Return the part of the query text to which this error is attributed, if
+    /// any.
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            StaticError::Parse { span, .. } |
+            StaticError::Regex { span, .. } |
+            StaticError::UnboundVar { span, .. } |
+            StaticError::Operator { span, .. } => *span,
+        }
+    }
 }
 
 impl<'input> From<ParseError<'input>> for StaticError {
     fn from(parse_error: ParseError<'input>) -> StaticError {
-        StaticError::Parse(parse_error.to_string())
+        // LALRPOP's own messages include the position as a byte offset, which
+        // isn't much help; we show the position by underlining it instead.
+        let (message, span) = match parse_error {
+            lalrpop_util::ParseError::InvalidToken { location } => {
+                ("invalid token".to_owned(), Span { start: location, end: location })
+            }
+            lalrpop_util::ParseError::UnrecognizedEOF { location, expected } => {
+                (format!("unexpected end of query{}", expecting(&expected)),
+                 Span { start: location, end: location })
+            }
+            lalrpop_util::ParseError::UnrecognizedToken { token: (start, token, end), expected } => {
+                (format!("unexpected '{}'{}", token, expecting(&expected)), Span { start, end })
+            }
+            lalrpop_util::ParseError::ExtraToken { token: (start, token, end) } => {
+                (format!("unexpected '{}' after end of query", token), Span { start, end })
+            }
+            // Errors the grammar's actions detect are already `StaticError`s.
+            lalrpop_util::ParseError::User { error } => return error,
+        };
+        StaticError::Parse { message, span: Some(span) }
     }
 }
+
+/// Describe the tokens the parser `expected`, if there are few enough of them
+/// to be helpful.
+fn expecting(expected: &[String]) -> String {
+    match expected {
+        [] => String::new(),
+        [only] => format!("; expected {}", only),
+        _ if expected.len() <= 5 => format!("; expected one of {}", expected.join(", ")),
+        _ => String::new(),
+    }
+}
+
+/// Given `error`, which arose from compiling or running the query `text`,
+/// return an error whose message also shows the line of `text` to which it is
+/// attributed, with carets underlining the offending part.
+///
+/// If `error` isn't attributed to any part of `text`, return it unchanged.
+pub fn show_in_query(error: anyhow::Error, text: &str) -> anyhow::Error {
+    let span = error.chain().find_map(|cause| {
+        if let Some(error) = cause.downcast_ref::<StaticError>() {
+            error.span()
+        } else if let Some(error) = cause.downcast_ref::<value::Error>() {
+            error.span()
+        } else {
+            None
+        }
+    });
+
+    match span {
+        Some(span) => anyhow!("{:#}\n{}", error, underline(text, span)),
+        None => error,
+    }
+}
+
+/// Return true if `error` arose from a traversal like `paths` reaching an edge
+/// whose referent isn't in the snapshot.
+pub fn is_dangling_edge(error: &anyhow::Error) -> bool {
+    error.chain().any(|cause| {
+        cause.downcast_ref::<value::Error>().is_some_and(value::Error::is_dangling_edge)
+    })
+}
+
+/// Return the line of `text` containing the start of `span`, followed by a line
+/// of carets underlining `span`, both indented. Underline at least one
+/// character, so that empty spans, like the end of the text, are visible.
+fn underline(text: &str, span: Span) -> String {
+    let start = span.start.min(text.len());
+    let line_start = text[..start].rfind('\n').map_or(0, |i| i + 1);
+    let line_end = text[start..].find('\n').map_or(text.len(), |i| start + i);
+    let end = span.end.clamp(start, line_end);
+
+    // Keep any tabs, so the carets line up.
+    let mut carets: String = text[line_start..start]
+        .chars()
+        .map(|ch| if ch == '\t' { '\t' } else { ' ' })
+        .collect();
+    let width = text[start..end].chars().count().max(1);
+    carets.push_str(&"^".repeat(width));
+
+    format!("    {}\n    {}", &text[line_start..line_end], carets)
+}
@@ -0,0 +1,260 @@
+//! Finding the paths by which the root retains a given node.
+//!
+//! Searching forward from the root for paths that end at a particular node
+//! explores everything reachable from the root. It's usually much cheaper to
+//! search backwards from the target node over incoming edges first, which only
+//! visits the nodes that can reach it, and then confine the forward searches
+//! to those nodes.
+//!
+//! To find more than one path, we use Yen's algorithm: each new path is the
+//! shortest detour from some prefix of the last path found that avoids the
+//! edges earlier paths took from that point. This takes one breadth-first
+//! search per step of each path returned, no matter how many cycles the graph
+//! contains.
+
+use super::breadth_first::Step;
+use crate::dump::{CoreDump, NodeId};
+
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet, VecDeque};
+
+/// Return up to `limit` distinct paths from `dump`'s root to `target`, shortest
+/// first. Each path is a vector of `Step`s leading from the root to `target`;
+/// if `target` is the root itself, return a single empty path. If `target` is
+/// not reachable from the root, return an empty vector.
+pub fn retaining_paths<'a>(dump: &'a CoreDump<'a>, target: NodeId, limit: usize)
+    -> Vec<Vec<Step<'a>>>
+{
+    let root = dump.get_root().id();
+    if limit == 0 {
+        return vec![];
+    }
+
+    // Search backwards from `target` to find the nodes that can reach it.
+    let mut reaches = HashSet::new();
+    reaches.insert(target);
+    let mut front = VecDeque::new();
+    front.push_back(target);
+    while let Some(id) = front.pop_front() {
+        for (origin, _) in dump.referrers(id) {
+            if reaches.insert(origin.id()) {
+                front.push_back(origin.id());
+            }
+        }
+    }
+
+    let search = Search { dump, reaches, target };
+    let Some(first) = search.shortest(root, &HashSet::new(), &HashSet::new()) else {
+        return vec![];
+    };
+
+    // `found` holds the paths we'll return, in order. `candidates` holds
+    // detours not yet taken, as indices into `pending`; among equally short
+    // candidates, it prefers those found earlier, so the results are
+    // deterministic. `seen` holds every path in either, so that no path is
+    // proposed twice.
+    let mut seen = HashSet::new();
+    seen.insert(first.clone());
+    let mut found = vec![first];
+    let mut pending = Vec::new();
+    let mut candidates = BinaryHeap::new();
+    while found.len() < limit {
+        let last = found.last().unwrap();
+        for (i, spur) in last.iter().enumerate() {
+            // Leave `spur.origin` by an edge that no path found so far takes
+            // after this same prefix, and don't revisit the prefix's nodes.
+            let prefix = &last[..i];
+            let taken: HashSet<Hop> = found.iter()
+                .filter(|path| path.len() > i && path[..i] == *prefix)
+                .map(|path| path[i])
+                .collect();
+            let visited: HashSet<NodeId> = prefix.iter().map(|hop| hop.origin).collect();
+            let Some(detour) = search.shortest(spur.origin, &visited, &taken) else {
+                continue;
+            };
+
+            let mut path = prefix.to_vec();
+            path.extend(detour);
+            if seen.insert(path.clone()) {
+                candidates.push(Reverse((path.len(), pending.len())));
+                pending.push(Some(path));
+            }
+        }
+
+        let Some(Reverse((_, index))) = candidates.pop() else {
+            break;
+        };
+        found.push(pending[index].take().unwrap());
+    }
+
+    found.iter()
+        .map(|path| path.iter().map(|hop| hop.step(dump)).collect())
+        .collect()
+}
+
+/// A step along a path, identifying the edge by its position among its
+/// origin's edges, so that parallel edges count as different paths.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+struct Hop {
+    origin: NodeId,
+    index: usize,
+    referent: NodeId,
+}
+
+impl Hop {
+    fn step<'a>(&self, dump: &'a CoreDump<'a>) -> Step<'a> {
+        // Hops only mention nodes present in the dump.
+        let edge = dump.get_node(self.origin).unwrap().edges().nth(self.index).unwrap();
+        Step { origin: self.origin, edge }
+    }
+}
+
+/// Forward searches towards `target`, confined to the nodes in `reaches`.
+struct Search<'a> {
+    dump: &'a CoreDump<'a>,
+    reaches: HashSet<NodeId>,
+    target: NodeId,
+}
+
+impl Search<'_> {
+    /// Return a shortest path from `start` to the target that avoids the nodes
+    /// in `visited` and doesn't begin with any hop in `taken`, if there is one.
+    fn shortest(&self, start: NodeId, visited: &HashSet<NodeId>, taken: &HashSet<Hop>)
+        -> Option<Vec<Hop>>
+    {
+        // Map each node reached to the hop by which we reached it.
+        let mut arrivals: HashMap<NodeId, Option<Hop>> = HashMap::new();
+        arrivals.insert(start, None);
+        let mut front = VecDeque::new();
+        front.push_back(start);
+        while let Some(id) = front.pop_front() {
+            if id == self.target {
+                let mut path = Vec::new();
+                let mut at = id;
+                while let Some(hop) = arrivals[&at] {
+                    path.push(hop);
+                    at = hop.origin;
+                }
+                path.reverse();
+                return Some(path);
+            }
+
+            // Every node in `reaches` is in the dump.
+            for (index, edge) in self.dump.get_node(id).unwrap().edges().enumerate() {
+                let Some(referent) = edge.referent else { continue };
+                let hop = Hop { origin: id, index, referent };
+                if !self.reaches.contains(&referent) ||
+                    visited.contains(&referent) ||
+                    arrivals.contains_key(&referent) ||
+                    (id == start && taken.contains(&hop))
+                {
+                    continue;
+                }
+                arrivals.insert(referent, Some(hop));
+                front.push_back(referent);
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::dump::test_utils::{node, snapshot};
+    use crate::dump::{CoreDump, NodeId};
+    use std::path::Path;
+    use super::retaining_paths;
+
+    /// Return the ids of the nodes along each of the paths from the root of
+    /// `dump` to `target`, as `retaining_paths` finds them.
+    fn paths(dump: &CoreDump, target: u64, limit: usize) -> Vec<Vec<u64>> {
+        retaining_paths(dump, NodeId(target), limit)
+            .iter()
+            .map(|path| {
+                let mut ids = vec![dump.get_root().id().0];
+                ids.extend(path.iter().map(|step| step.edge.referent.unwrap().0));
+                ids
+            })
+            .collect()
+    }
+
+    #[test]
+    fn shortest_first() {
+        // From the root 1, there are paths to 9 through 2 (length 2), through
+        // 5 and 6 (length 3), and through 2, 3, and 4 (length 4). The first
+        // edge of the longest path is also the first edge of the shortest.
+        let bytes = snapshot(&[
+            node(1, 8, &[(2, "a"), (5, "b")], &[]),
+            node(2, 8, &[(9, "c"), (3, "d")], &[]),
+            node(3, 8, &[(4, "e")], &[]),
+            node(4, 8, &[(9, "f")], &[]),
+            node(5, 8, &[(6, "g")], &[]),
+            node(6, 8, &[(9, "h")], &[]),
+            node(9, 8, &[(1, "i")], &[]),
+        ]);
+        let dump = CoreDump::from_bytes(Path::new("shortest"), &bytes).unwrap();
+        assert_eq!(paths(&dump, 9, 1), vec![vec![1, 2, 9]]);
+        assert_eq!(paths(&dump, 9, 2), vec![vec![1, 2, 9], vec![1, 5, 6, 9]]);
+        assert_eq!(paths(&dump, 9, 10), vec![vec![1, 2, 9], vec![1, 5, 6, 9], vec![1, 2, 3, 4, 9]]);
+    }
+
+    #[test]
+    fn edge_cases() {
+        let bytes = snapshot(&[
+            node(1, 8, &[(2, "a")], &[]),
+            node(2, 8, &[(1, "b")], &[]),
+            node(3, 8, &[(2, "c")], &[]),
+        ]);
+        let dump = CoreDump::from_bytes(Path::new("edges"), &bytes).unwrap();
+
+        // The root retains itself by the empty path, even if there are cycles
+        // through it.
+        assert_eq!(paths(&dump, 1, 3), vec![vec![1]]);
+
+        // Nothing retains 3.
+        assert_eq!(paths(&dump, 3, 3), Vec::<Vec<u64>>::new());
+
+        // Asking for no paths finds none.
+        assert_eq!(paths(&dump, 2, 0), Vec::<Vec<u64>>::new());
+        assert_eq!(paths(&dump, 2, 1), vec![vec![1, 2]]);
+    }
+
+    #[test]
+    fn cycle_dense() {
+        // The root 1 reaches 100 only through 2, but 2 also leads into a
+        // complete graph on the nodes 3 through 12, every one of which leads
+        // back to 2. There are a great many paths through that tangle, but
+        // only one retaining path.
+        let tangle: Vec<(u64, String)> = (3..13).map(|id| (id, format!("t{}", id))).collect();
+        let mut nodes = vec![node(1, 8, &[(2, "a")], &[])];
+        let mut edges_of_2 = vec![(100, "b")];
+        edges_of_2.extend(tangle.iter().map(|(id, name)| (*id, name.as_str())));
+        nodes.push(node(2, 8, &edges_of_2, &[]));
+        for &(id, _) in &tangle {
+            let mut edges = vec![(2, "back")];
+            edges.extend(tangle.iter().filter(|(other, _)| *other != id).map(|(other, name)| (*other, name.as_str())));
+            nodes.push(node(id, 8, &edges, &[]));
+        }
+        nodes.push(node(100, 8, &[], &[]));
+        let bytes = snapshot(&nodes);
+        let dump = CoreDump::from_bytes(Path::new("tangle"), &bytes).unwrap();
+
+        assert_eq!(paths(&dump, 100, 3), vec![vec![1, 2, 100]]);
+        assert_eq!(paths(&dump, 100, 1000), vec![vec![1, 2, 100]]);
+    }
+
+    #[test]
+    fn parallel_edges() {
+        // Two edges from 1 to 2 make two distinct paths.
+        let bytes = snapshot(&[
+            node(1, 8, &[(2, "a"), (2, "b")], &[]),
+            node(2, 8, &[], &[]),
+        ]);
+        let dump = CoreDump::from_bytes(Path::new("parallel"), &bytes).unwrap();
+        let names: Vec<Vec<String>> = retaining_paths(&dump, NodeId(2), 5)
+            .iter()
+            .map(|path| path.iter().map(|step| step.edge.name.as_ref().unwrap().to_string()).collect())
+            .collect();
+        assert_eq!(names, vec![vec!["a"], vec!["b"]]);
+    }
+}
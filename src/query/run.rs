@@ -7,33 +7,68 @@
 
 use fallible_iterator::{self, FallibleIterator};
 
-use super::ast::{Expr, LambdaId, Predicate, PredicateOp, Var};
+use super::ast::{ArithOp, CompareOp, Expr, ExprKind, LambdaId, Predicate, PredicateKind, PredicateOp, Span, Var};
 use super::breadth_first::{BreadthFirst, Step};
+use super::retaining::retaining_paths;
 use super::fun::{CaptureList, StaticAnalysis, plan_lexical, plan_activation, plan_lambda};
 use super::Activation;
 use super::Context;
-use super::value::{self, Callable, EvalResult, Function, Stream, TryUnwrap, Value};
+use super::value::{self, get_field, Callable, EvalResult, Function, Key, Record, Stream, TryUnwrap, Value};
 use super::{Plan, PredicatePlan};
-use crate::dump::{Edge, Node, NodeId};
+use crate::dump::{CoreDump, Node, NodeId};
 
 use std::borrow::Cow;
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BinaryHeap, HashMap};
+use std::collections::hash_map::Entry;
 use std::fmt;
 use std::iter::once;
+use std::ptr;
 use std::rc::Rc;
+use std::slice;
 
 /// Given the expression `expr`, return a `Plan` that will evaluate it.
+///
+/// If `expr` has a position in the query text, then errors the plan reports are
+/// attributed to that position, unless some subexpression has already claimed
+/// them.
 pub fn plan_expr(expr: &Expr, analysis: &StaticAnalysis) -> Box<dyn Plan> {
-    match expr {
-        Expr::Number(n) => Box::new(Const(*n)),
-        Expr::String(s) => Box::new(Const(s.clone())),
-        Expr::StreamLiteral(elts) => {
+    let plan: Box<dyn Plan> = match &expr.kind {
+        ExprKind::Number(n) => Box::new(Const(*n)),
+        ExprKind::String(s) => Box::new(Const(s.clone())),
+        ExprKind::Bool(b) => Box::new(Const(*b)),
+        ExprKind::StreamLiteral(elts) => {
             Box::new(StreamLiteral(elts.iter().map(|b| plan_expr(b, analysis)).collect()))
         }
-        Expr::PredicateOp { id, stream, op, predicate } => plan_stream(*id, op, stream, predicate, analysis),
+        ExprKind::PredicateOp { id, stream, op, predicate } => plan_stream(*id, op, stream, predicate, analysis),
+        ExprKind::Record(fields) => {
+            Box::new(RecordLiteral(fields.iter()
+                                   .map(|(name, value)| (name.clone(), plan_expr(value, analysis)))
+                                   .collect()))
+        }
+        ExprKind::Project { arg, field } => Box::new(Project {
+            arg: plan_expr(arg, analysis),
+            field: field.clone(),
+        }),
+        ExprKind::Predicate { id, predicate } => Box::new(PredicateExpr {
+            capture_list: analysis.get_capture_list(*id),
+            predicate: plan_predicate(predicate, analysis).into_plan().into(),
+        }),
+
+        ExprKind::Arith { op, left, right } => Box::new(Arith {
+            op: *op,
+            left: plan_expr(left, analysis),
+            right: plan_expr(right, analysis),
+        }),
 
-        Expr::Var(var) => plan_var(var, analysis),
-        Expr::Lambda { id, formals, body } => plan_lambda(*id, formals, body, analysis),
-        Expr::App { arg, fun } => plan_app(arg, fun, analysis),
+        ExprKind::Var(var) => plan_var(var, analysis),
+        ExprKind::Lambda { id, formals, body } => plan_lambda(*id, formals, body, analysis),
+        ExprKind::App { arg, fun } => plan_app(arg, fun, analysis),
+    };
+
+    match expr.span {
+        Some(span) => Box::new(Located { span, plan }),
+        None => plan,
     }
 }
 
@@ -41,9 +76,18 @@ fn plan_var(var: &Var, analysis: &StaticAnalysis) -> Box<dyn Plan> {
     match var {
         Var::Root => Box::new(Root),
         Var::Nodes => Box::new(Nodes),
+        Var::Added => Box::new(Added),
+        Var::Removed => Box::new(Removed),
+        Var::Retained => Box::new(Retained),
         Var::Map => Box::new(Map),
+        Var::GroupBy => Box::new(GroupBy),
+        Var::SortBy => Box::new(SortBy),
+        Var::Top => Box::new(Top),
+        Var::Output(n) => Box::new(Output(*n)),
         Var::Lexical { id, name } => plan_lexical(*id, name, analysis),
-        _ => unimplemented!("plan_var"),
+        // `StaticAnalysis` only allows these to be applied, which `plan_app`
+        // handles.
+        _ => unreachable!("built-in operator {:?} used as a value", var),
     }
 }
 
@@ -51,10 +95,23 @@ fn plan_app(arg: &Expr, fun: &Expr, analysis: &StaticAnalysis) -> Box<dyn Plan>
     let arg_plan = plan_expr(arg, analysis);
 
     // Handle direct applications of certain built-in functions.
-    match fun {
-        Expr::Var(Var::Edges) => Box::new(Edges(arg_plan)),
-        Expr::Var(Var::First) => Box::new(First(arg_plan)),
-        Expr::Var(Var::Paths) => Box::new(Paths(arg_plan)),
+    match &fun.kind {
+        ExprKind::Var(Var::Avg) => Box::new(Avg(arg_plan)),
+        ExprKind::Var(Var::CensusDiff) => Box::new(CensusDiff(arg_plan)),
+        ExprKind::Var(Var::Count) => Box::new(Count(arg_plan)),
+        ExprKind::Var(Var::Dominated) => Box::new(Dominated(arg_plan)),
+        ExprKind::Var(Var::Edges) => Box::new(Edges(arg_plan)),
+        ExprKind::Var(Var::First) => Box::new(First(arg_plan)),
+        ExprKind::Var(Var::Idom) => Box::new(Idom(arg_plan)),
+        ExprKind::Var(Var::Max) => Box::new(Max(arg_plan)),
+        ExprKind::Var(Var::Min) => Box::new(Min(arg_plan)),
+        ExprKind::Var(Var::Paths) => Box::new(Paths(arg_plan)),
+        ExprKind::Var(Var::PathsTo) => Box::new(PathsTo { target: arg_plan, limit: None }),
+        ExprKind::App { arg: limit, fun } if fun.kind == ExprKind::Var(Var::PathsTo) => {
+            Box::new(PathsTo { target: arg_plan, limit: Some(plan_expr(limit, analysis)) })
+        }
+        ExprKind::Var(Var::Referrers) => Box::new(Referrers(arg_plan)),
+        ExprKind::Var(Var::Sum) => Box::new(Sum(arg_plan)),
         _ => {
             let fun_plan = plan_expr(fun, analysis);
             plan_activation(arg_plan, fun_plan)
@@ -68,12 +125,18 @@ fn plan_stream(id: LambdaId,
                predicate: &Predicate,
                analysis: &StaticAnalysis)
                -> Box<dyn Plan> {
-    //let stream_plan = plan_expr(stream);
-    //let predicate_plan = plan_predicate(predicate);
+    if *op == PredicateOp::Filter {
+        return plan_filter(id, stream, predicate, analysis);
+    }
+
+    let stream = plan_expr(stream, analysis);
+    let capture_list = analysis.get_capture_list(id);
+    let predicate: Rc<dyn PredicatePlan> = plan_predicate(predicate, analysis).into_plan().into();
     match op {
-        PredicateOp::Find => unimplemented!("PredicateOp::Find"),
-        PredicateOp::Filter => plan_filter(id, stream, predicate, analysis),
-        PredicateOp::Until => unimplemented!("PredicateOp::Until"),
+        PredicateOp::Find => Box::new(Find { stream, capture_list, predicate }),
+        PredicateOp::Until => Box::new(Until { stream, capture_list, predicate, inclusive: false }),
+        PredicateOp::Through => Box::new(Until { stream, capture_list, predicate, inclusive: true }),
+        PredicateOp::Filter => unreachable!(),
     }
 }
 
@@ -83,8 +146,8 @@ fn plan_filter(id: LambdaId, stream: &Expr, predicate: &Predicate, analysis: &St
 
     // Can we implement `nodes { id: ... }` using `NodesById`, rather than a
     // linear search over all nodes?
-    match stream {
-        Expr::Var(Var::Nodes) => {
+    match &stream.kind {
+        ExprKind::Var(Var::Nodes) => {
             if let Some((id, remainder)) = find_predicate_required_id(predicate) {
                 stream_plan = Box::new(NodesById(plan_expr(id, analysis)));
                 predicate_plan = plan_junction::<And>(&remainder, analysis);
@@ -93,7 +156,7 @@ fn plan_filter(id: LambdaId, stream: &Expr, predicate: &Predicate, analysis: &St
                 predicate_plan = plan_predicate(predicate, analysis);
             }
         }
-        stream => {
+        _ => {
             stream_plan = plan_expr(stream, analysis);
             predicate_plan = plan_predicate(predicate, analysis);
         }
@@ -136,36 +199,63 @@ impl PlanOrTrivial {
             trivial @ PlanOrTrivial::Trivial(_) => trivial,
         }
     }
+
+    /// Return a plan for this predicate, even if its result is known in
+    /// advance.
+    fn into_plan(self) -> Box<dyn PredicatePlan> {
+        match self {
+            PlanOrTrivial::Plan(plan) => plan,
+            // An empty conjunction is always true; an empty disjunction, false.
+            PlanOrTrivial::Trivial(true) => Box::new(And(vec![])),
+            PlanOrTrivial::Trivial(false) => Box::new(Or(vec![])),
+        }
+    }
 }
 
+/// Given `predicate`, return a `PredicatePlan` that will evaluate it, or the
+/// value it always produces. Errors are attributed to `predicate`'s position in
+/// the query text, as for `plan_expr`.
 fn plan_predicate(predicate: &Predicate, analysis: &StaticAnalysis) -> PlanOrTrivial {
     use self::PlanOrTrivial::*;
-    match predicate {
-        Predicate::Expr(expr) => Plan(Box::new(EqualPredicate(plan_expr(expr, analysis)))),
-        Predicate::Field(field_name, sub) => plan_predicate(sub, analysis).map_plan(|predicate| {
+    let plan = match &predicate.kind {
+        PredicateKind::Expr(expr) => Plan(Box::new(EqualPredicate(plan_expr(expr, analysis)))),
+        PredicateKind::Field(field_name, sub) => plan_predicate(sub, analysis).map_plan(|predicate| {
             Box::new(FieldPredicate {
                 field_name: field_name.clone(),
                 predicate,
             })
         }),
-        Predicate::Ends(sub) => plan_predicate(sub, analysis).map_plan(|subplan| Box::new(Ends(subplan))),
-        Predicate::Any(sub) => match plan_predicate(sub, analysis) {
+        PredicateKind::Ends(sub) => plan_predicate(sub, analysis).map_plan(|subplan| Box::new(Ends(subplan))),
+        PredicateKind::Any(sub) => match plan_predicate(sub, analysis) {
             PlanOrTrivial::Plan(p) => Plan(Box::new(Any(p))),
             PlanOrTrivial::Trivial(false) => Trivial(false),
             PlanOrTrivial::Trivial(true) => Plan(Box::new(NonEmpty)),
         },
-        Predicate::All(sub) => match plan_predicate(sub, analysis) {
+        PredicateKind::All(sub) => match plan_predicate(sub, analysis) {
             PlanOrTrivial::Plan(p) => Plan(Box::new(All(p))),
             PlanOrTrivial::Trivial(true) => Trivial(true),
             PlanOrTrivial::Trivial(false) => Plan(Box::new(Empty)),
         },
-        Predicate::Regex(regex) => Plan(Box::new(Regex((&**regex).clone()))),
-        Predicate::And(predicates) => plan_junction::<And>(predicates, analysis),
-        Predicate::Or(predicates) => plan_junction::<Or>(predicates, analysis),
-        Predicate::Not(predicate) => match plan_predicate(predicate, analysis) {
+        PredicateKind::Regex(regex) => Plan(Box::new(Regex((**regex).clone()))),
+        PredicateKind::Compare(op, operand) => Plan(Box::new(Compare {
+            op: *op,
+            operand: plan_expr(operand, analysis),
+        })),
+        PredicateKind::Range(low, high) => Plan(Box::new(Range {
+            low: low.as_ref().map(|low| plan_expr(low, analysis)),
+            high: high.as_ref().map(|high| plan_expr(high, analysis)),
+        })),
+        PredicateKind::And(predicates) => plan_junction::<And>(predicates, analysis),
+        PredicateKind::Or(predicates) => plan_junction::<Or>(predicates, analysis),
+        PredicateKind::Not(predicate) => match plan_predicate(predicate, analysis) {
             Trivial(k) => Trivial(!k),
             Plan(p) => Plan(Box::new(Not(p))),
         },
+    };
+
+    match predicate.span {
+        Some(span) => plan.map_plan(|predicate| Box::new(LocatedPredicate { span, predicate })),
+        None => plan,
     }
 }
 
@@ -180,14 +270,14 @@ fn plan_predicate(predicate: &Predicate, analysis: &StaticAnalysis) -> PlanOrTri
 /// Since we use `Box` and not `Rc` in our parse tree, this could end up copying
 /// a lot if the remainder predicate is large.
 fn find_predicate_required_id(predicate: &Predicate) -> Option<(&Expr, Vec<Predicate>)> {
-    match predicate {
-        Predicate::Field(name, id_predicate) if name == "id" => {
-            if let Predicate::Expr(id_expr) = &**id_predicate {
+    match &predicate.kind {
+        PredicateKind::Field(name, id_predicate) if name == "id" => {
+            if let PredicateKind::Expr(id_expr) = &id_predicate.kind {
                 return Some((id_expr, vec![]));
             }
         }
 
-        Predicate::And(predicates) => {
+        PredicateKind::And(predicates) => {
             // Search the sub-predicates of this conjunction for one that
             // requires a specific id.
             if let Some((i, id, child_remainder)) =
@@ -225,6 +315,33 @@ fn splice<T: Clone>(slice: &[T], i: usize, v: Vec<T>) -> Vec<T> {
     }
 }
 
+/// A plan that attributes the errors `plan` reports to `span` in the query
+/// text, unless they have a position already.
+#[derive(Debug)]
+struct Located {
+    span: Span,
+    plan: Box<dyn Plan>,
+}
+
+impl Plan for Located {
+    fn run<'a, 'd>(&self, act: &'a Activation<'a, 'd>, cx: &Context<'d>) -> EvalResult<'d> {
+        self.plan.run(act, cx).map_err(|error| error.at(self.span))
+    }
+}
+
+/// A predicate plan that attributes errors to `span`, like `Located`.
+#[derive(Debug)]
+struct LocatedPredicate {
+    span: Span,
+    predicate: Box<dyn PredicatePlan>,
+}
+
+impl PredicatePlan for LocatedPredicate {
+    fn test<'a, 'd>(&self, value: &Value<'d>, act: &Activation<'a, 'd>, cx: &Context<'d>) -> Result<bool, value::Error> {
+        self.predicate.test(value, act, cx).map_err(|error| error.at(self.span))
+    }
+}
+
 #[derive(Debug)]
 struct Const<T: fmt::Debug>(T);
 
@@ -249,6 +366,44 @@ impl Plan for StreamLiteral {
     }
 }
 
+#[derive(Debug)]
+struct Arith {
+    op: ArithOp,
+    left: Box<dyn Plan>,
+    right: Box<dyn Plan>,
+}
+
+impl Plan for Arith {
+    fn run<'a, 'd>(&self, act: &'a Activation<'a, 'd>, cx: &Context<'d>) -> EvalResult<'d> {
+        let left: u64 = self.left.run(act, cx)?.try_unwrap()?;
+        let right: u64 = self.right.run(act, cx)?.try_unwrap()?;
+        let result = match self.op {
+            ArithOp::Add => left.checked_add(right),
+            ArithOp::Sub => left.checked_sub(right),
+            ArithOp::Mul => left.checked_mul(right),
+            ArithOp::Div => {
+                if right == 0 {
+                    return Err(value::Error::DivideByZero);
+                }
+                Some(left / right)
+            }
+        };
+        result.map(Value::from).ok_or(value::Error::Overflow)
+    }
+}
+
+#[derive(Debug)]
+struct RecordLiteral(Vec<(String, Box<dyn Plan>)>);
+
+impl Plan for RecordLiteral {
+    fn run<'a, 'd>(&self, act: &'a Activation<'a, 'd>, cx: &Context<'d>) -> EvalResult<'d> {
+        let fields = self.0.iter()
+            .map(|(name, plan)| Ok((name.clone(), plan.run(act, cx)?)))
+            .collect::<Result<_, value::Error>>()?;
+        Ok(Value::from(Rc::new(Record::new(fields))))
+    }
+}
+
 #[derive(Debug)]
 struct First(Box<dyn Plan>);
 impl Plan for First {
@@ -262,6 +417,106 @@ impl Plan for First {
     }
 }
 
+#[derive(Debug)]
+struct Count(Box<dyn Plan>);
+impl Plan for Count {
+    fn run<'a, 'd>(&self, act: &'a Activation<'a, 'd>, cx: &Context<'d>) -> EvalResult<'d> {
+        let stream: Stream = self.0.run(act, cx)?.try_unwrap()?;
+        Ok(Value::from(stream.count()? as u64))
+    }
+}
+
+#[derive(Debug)]
+struct Sum(Box<dyn Plan>);
+impl Plan for Sum {
+    fn run<'a, 'd>(&self, act: &'a Activation<'a, 'd>, cx: &Context<'d>) -> EvalResult<'d> {
+        let stream: Stream = self.0.run(act, cx)?.try_unwrap()?;
+        let sum = stream.fold(0_u64, |sum, value| {
+            let n: u64 = value.try_unwrap()?;
+            sum.checked_add(n).ok_or(value::Error::Overflow)
+        })?;
+        Ok(Value::from(sum))
+    }
+}
+
+#[derive(Debug)]
+struct Min(Box<dyn Plan>);
+impl Plan for Min {
+    fn run<'a, 'd>(&self, act: &'a Activation<'a, 'd>, cx: &Context<'d>) -> EvalResult<'d> {
+        let stream: Stream = self.0.run(act, cx)?.try_unwrap()?;
+        let min = fold_numbers(stream, u64::min)?;
+        Ok(Value::from(min.ok_or(value::Error::EmptyStream)?))
+    }
+}
+
+#[derive(Debug)]
+struct Max(Box<dyn Plan>);
+impl Plan for Max {
+    fn run<'a, 'd>(&self, act: &'a Activation<'a, 'd>, cx: &Context<'d>) -> EvalResult<'d> {
+        let stream: Stream = self.0.run(act, cx)?.try_unwrap()?;
+        let max = fold_numbers(stream, u64::max)?;
+        Ok(Value::from(max.ok_or(value::Error::EmptyStream)?))
+    }
+}
+
+/// Combine the numbers in `stream` using `f`. Return `None` if the stream is
+/// empty.
+fn fold_numbers<F>(stream: Stream, f: F) -> Result<Option<u64>, value::Error>
+where
+    F: Fn(u64, u64) -> u64,
+{
+    stream.fold(None, |acc, value| {
+        let n: u64 = value.try_unwrap()?;
+        Ok(Some(acc.map_or(n, |acc| f(acc, n))))
+    })
+}
+
+#[derive(Debug)]
+struct Avg(Box<dyn Plan>);
+impl Plan for Avg {
+    fn run<'a, 'd>(&self, act: &'a Activation<'a, 'd>, cx: &Context<'d>) -> EvalResult<'d> {
+        let stream: Stream = self.0.run(act, cx)?.try_unwrap()?;
+        let (sum, count) = stream.fold((0_u64, 0), |(sum, count), value| {
+            let n: u64 = value.try_unwrap()?;
+            let sum = sum.checked_add(n).ok_or(value::Error::Overflow)?;
+            Ok((sum, count + 1))
+        })?;
+        if count == 0 {
+            return Err(value::Error::EmptyStream);
+        }
+        // We have no fractional numbers, so round down.
+        Ok(Value::from(sum / count))
+    }
+}
+
+/// A field projection, `ARG field`. If `ARG` is a stream, produce a stream of
+/// the values of the field in each element, skipping elements where it is
+/// absent.
+#[derive(Debug)]
+struct Project {
+    arg: Box<dyn Plan>,
+    field: String,
+}
+impl Plan for Project {
+    fn run<'a, 'd>(&self, act: &'a Activation<'a, 'd>, cx: &Context<'d>) -> EvalResult<'d> {
+        let stream = match self.arg.run(act, cx)? {
+            Value::Stream(stream) => stream,
+            other => {
+                return get_field(&other, &self.field, cx)?
+                    .ok_or_else(|| value::Error::AbsentField { field: self.field.clone() });
+            }
+        };
+
+        let field = self.field.clone();
+        let cx = cx.clone();
+        let iter = stream
+            .map(move |item| get_field(&item, &field, &cx))
+            .filter(|field| Ok(field.is_some()))
+            .map(|field| Ok(field.unwrap()));
+        Ok(Value::from(Stream::new(iter)))
+    }
+}
+
 #[derive(Debug)]
 struct Root;
 impl Plan for Root {
@@ -279,6 +534,114 @@ impl Plan for Nodes {
     }
 }
 
+/// A reference to an earlier query's result, `%N` or `%`.
+#[derive(Debug)]
+struct Output(Option<usize>);
+impl Plan for Output {
+    fn run<'a, 'd>(&self, _act: &'a Activation<'a, 'd>, cx: &Context<'d>) -> EvalResult<'d> {
+        cx.output(self.0)
+    }
+}
+
+/// Return the baseline snapshot from `cx`, or an error if there is none.
+fn get_baseline<'d>(cx: &Context<'d>) -> Result<&'d CoreDump<'d>, value::Error> {
+    cx.baseline.ok_or(value::Error::NoBaseline)
+}
+
+/// The nodes in the current snapshot whose ids do not appear in the baseline.
+#[derive(Debug)]
+struct Added;
+impl Plan for Added {
+    fn run<'a, 'd>(&self, _act: &'a Activation<'a, 'd>, cx: &Context<'d>) -> EvalResult<'d> {
+        let baseline = get_baseline(cx)?;
+        let iter = cx.dump.nodes()
+            .filter(move |n| !baseline.has_node(n.id()))
+            .map(|n| Ok(n.into()));
+        Ok(Value::from(Stream::new(fallible_iterator::convert(iter))))
+    }
+}
+
+/// The nodes in the baseline snapshot whose ids do not appear in the current
+/// snapshot.
+#[derive(Debug)]
+struct Removed;
+impl Plan for Removed {
+    fn run<'a, 'd>(&self, _act: &'a Activation<'a, 'd>, cx: &Context<'d>) -> EvalResult<'d> {
+        let baseline = get_baseline(cx)?;
+        let dump = cx.dump;
+        let iter = baseline.nodes()
+            .filter(move |n| !dump.has_node(n.id()))
+            .map(|n| Ok(n.into()));
+        Ok(Value::from(Stream::new(fallible_iterator::convert(iter))))
+    }
+}
+
+/// The nodes in the current snapshot whose ids also appear in the baseline.
+#[derive(Debug)]
+struct Retained;
+impl Plan for Retained {
+    fn run<'a, 'd>(&self, _act: &'a Activation<'a, 'd>, cx: &Context<'d>) -> EvalResult<'d> {
+        let baseline = get_baseline(cx)?;
+        let iter = cx.dump.nodes()
+            .filter(move |n| baseline.has_node(n.id()))
+            .map(|n| Ok(n.into()));
+        Ok(Value::from(Stream::new(fallible_iterator::convert(iter))))
+    }
+}
+
+/// Compare censuses of the baseline and current snapshots, grouped by the key
+/// `KEY` in `KEY censusdiff`.
+#[derive(Debug)]
+struct CensusDiff(Box<dyn Plan>);
+impl Plan for CensusDiff {
+    fn run<'a, 'd>(&self, act: &'a Activation<'a, 'd>, cx: &Context<'d>) -> EvalResult<'d> {
+        let key = self.0.run(act, cx)?;
+        let baseline = get_baseline(cx)?;
+
+        // Field lookups on the baseline's nodes must consult the baseline.
+        let baseline_cx = Context::from_dump(baseline);
+        let nodes = |dump: &'d CoreDump<'d>| {
+            Stream::new(fallible_iterator::convert(dump.nodes().map(|n| Ok(n.into()))))
+        };
+        let old = census(nodes(baseline), &key, &baseline_cx)?;
+        let new = census(nodes(cx.dump), &key, cx)?;
+
+        // Merge the two censuses: for each key, its value, and the counts and
+        // sizes in the old and new snapshots.
+        let mut merged: Vec<(Value<'d>, [u64; 4])> = Vec::new();
+        let mut index: HashMap<Key, usize> = HashMap::new();
+        for (offset, groups) in [(0, old), (2, new)] {
+            for (key, count, bytes) in groups {
+                let i = match index.entry(Key::from_value(&key)?) {
+                    Entry::Occupied(entry) => *entry.get(),
+                    Entry::Vacant(entry) => {
+                        merged.push((key, [0; 4]));
+                        *entry.insert(merged.len() - 1)
+                    }
+                };
+                merged[i].1[offset] = count;
+                merged[i].1[offset + 1] = bytes;
+            }
+        }
+
+        // Whatever grew the most comes first.
+        merged.sort_by_key(|&(_, [_, old_bytes, _, new_bytes])| {
+            Reverse(i128::from(new_bytes) - i128::from(old_bytes))
+        });
+
+        let iter = merged.into_iter().map(|(key, [old_count, old_bytes, new_count, new_bytes])| {
+            Ok(Value::from(Rc::new(Record::new(vec![
+                ("key".to_owned(), key),
+                ("oldCount".to_owned(), Value::from(old_count)),
+                ("newCount".to_owned(), Value::from(new_count)),
+                ("oldBytes".to_owned(), Value::from(old_bytes)),
+                ("newBytes".to_owned(), Value::from(new_bytes)),
+            ]))))
+        });
+        Ok(Value::from(Stream::new(fallible_iterator::convert(iter))))
+    }
+}
+
 #[derive(Debug)]
 struct NodesById(Box<dyn Plan>);
 impl Plan for NodesById {
@@ -296,13 +659,55 @@ struct Edges(Box<dyn Plan>);
 impl Plan for Edges {
     fn run<'a, 'd>(&self, act: &'a Activation<'a, 'd>, cx: &Context<'d>) -> EvalResult<'d> {
         let value = self.0.run(act, cx)?;
-        let node: &Node = value.try_unwrap()?;
-        let iter = node.edges.iter().map(|e| Ok(Value::from(e)));
+        let node: Node = value.try_unwrap()?;
+        let iter = node.edges().map(|e| Ok(Value::from(e)));
         let iter = fallible_iterator::convert(iter);
         Ok(Value::from(Stream::new(iter)))
     }
 }
 
+#[derive(Debug)]
+struct Referrers(Box<dyn Plan>);
+impl Plan for Referrers {
+    fn run<'a, 'd>(&self, act: &'a Activation<'a, 'd>, cx: &Context<'d>) -> EvalResult<'d> {
+        let value = self.0.run(act, cx)?;
+        let node: Node = value.try_unwrap()?;
+
+        // Produce each referring edge as a two-element stream, `[ ORIGIN EDGE ]`.
+        let iter = node.dump().referrers(node.id()).map(|(origin, edge)| {
+            let pair = vec![Ok(Value::from(origin)), Ok(Value::from(edge))];
+            Ok(Value::from(Stream::new(fallible_iterator::convert(pair.into_iter()))))
+        });
+        Ok(Value::from(Stream::new(fallible_iterator::convert(iter))))
+    }
+}
+
+#[derive(Debug)]
+struct Idom(Box<dyn Plan>);
+impl Plan for Idom {
+    fn run<'a, 'd>(&self, act: &'a Activation<'a, 'd>, cx: &Context<'d>) -> EvalResult<'d> {
+        let value = self.0.run(act, cx)?;
+        let node: Node = value.try_unwrap()?;
+        let dump = node.dump();
+        let idom = dump.immediate_dominator(node.id())
+            .ok_or(value::Error::NoImmediateDominator)?;
+        Ok(Value::from(idom))
+    }
+}
+
+#[derive(Debug)]
+struct Dominated(Box<dyn Plan>);
+impl Plan for Dominated {
+    fn run<'a, 'd>(&self, act: &'a Activation<'a, 'd>, cx: &Context<'d>) -> EvalResult<'d> {
+        let value = self.0.run(act, cx)?;
+        let node: Node = value.try_unwrap()?;
+        let dump = node.dump();
+        let iter = dump.dominated(node.id())
+            .map(|n| Ok(Value::from(n)));
+        Ok(Value::from(Stream::new(fallible_iterator::convert(iter))))
+    }
+}
+
 #[derive(Debug)]
 struct Filter {
     stream: Box<dyn Plan>,
@@ -311,38 +716,184 @@ struct Filter {
 }
 
 impl Plan for Filter {
+    fn run<'a, 'd>(&self, act: &'a Activation<'a, 'd>, cx: &Context<'d>) -> EvalResult<'d> {
+        let value = self.stream.run(act, cx)?;
+        let captured = act.get_captured(&self.capture_list);
+        apply_predicate(&self.filter, captured, value, cx)
+    }
+}
+
+/// Apply the predicate `filter` to `value`, using `captured` as the values of
+/// the variables it captures. If `value` is a stream, produce a stream of the
+/// elements that match; otherwise, produce a boolean.
+fn apply_predicate<'d>(filter: &Rc<dyn PredicatePlan>,
+                       captured: Vec<Value<'d>>,
+                       value: Value<'d>,
+                       cx: &Context<'d>)
+                       -> EvalResult<'d>
+{
+    let stream = match value {
+        Value::Stream(stream) => stream,
+        other => {
+            let matched = filter.test(&other, &Activation::from_captured(&captured), cx)?;
+            return Ok(Value::from(matched));
+        }
+    };
+
+    // Gather up owned versions of everything filter's argument needs.
+    let filter = filter.clone();
+    let cx = cx.clone();
+
+    // The `move` closure here takes ownership of all the parts it needs, so
+    // the stream becomes independent of this frame.
+    let iter = stream.filter(move |item| {
+        filter.test(item, &Activation::from_captured(&captured), &cx)
+    });
+    Ok(Value::from(Stream::new(iter)))
+}
+
+/// A predicate expression, `{ P, ... }`, which evaluates to a
+/// `PredicateFunction`.
+#[derive(Debug)]
+struct PredicateExpr {
+    capture_list: CaptureList,
+    predicate: Rc<dyn PredicatePlan>,
+}
+
+impl Plan for PredicateExpr {
+    fn run<'a, 'd>(&self, act: &'a Activation<'a, 'd>, _cx: &Context<'d>) -> EvalResult<'d> {
+        Ok(Value::from(Function::new(PredicateFunction {
+            predicate: self.predicate.clone(),
+            captured: act.get_captured(&self.capture_list),
+        })))
+    }
+}
+
+/// The function produced by evaluating a predicate expression. Applied to a
+/// stream, it filters the stream; applied to anything else, it returns a
+/// boolean.
+struct PredicateFunction<'d> {
+    predicate: Rc<dyn PredicatePlan>,
+    captured: Vec<Value<'d>>,
+}
+
+impl<'dump> Callable<'dump> for PredicateFunction<'dump> {
+    fn call_exact_arity(&self, actuals: &[Value<'dump>], cx: &Context<'dump>)
+                        -> EvalResult<'dump>
+    {
+        assert_eq!(actuals.len(), 1);
+        apply_predicate(&self.predicate, self.captured.clone(), actuals[0].clone(), cx)
+    }
+
+    fn arity(&self) -> usize {
+        1
+    }
+
+    fn name(&self) -> Cow<str> {
+        Cow::Borrowed("predicate")
+    }
+}
+
+/// The `find` operator: `STREAM find P` produces the first element of `STREAM`
+/// that matches `P`.
+#[derive(Debug)]
+struct Find {
+    stream: Box<dyn Plan>,
+    capture_list: CaptureList,
+    predicate: Rc<dyn PredicatePlan>,
+}
+
+impl Plan for Find {
     fn run<'a, 'd>(&self, act: &'a Activation<'a, 'd>, cx: &Context<'d>) -> EvalResult<'d> {
         let value = self.stream.run(act, cx)?;
         let stream: Stream = value.try_unwrap()?;
 
-        // Gather up owned versions of everything filter's argument needs.
         let captured = act.get_captured(&self.capture_list);
-        let filter = self.filter.clone();
-        let cx = cx.clone();
+        let act = Activation::from_captured(&captured);
+        let mut matches = stream.filter(|item| self.predicate.test(item, &act, cx));
+        matches.next()?.ok_or(value::Error::NoMatch)
+    }
+}
 
-        // The `move` closure here takes ownership of all the parts it needs, so
-        // the stream becomes independent of this frame.
-        let iter = stream.filter(move |item| {
-            filter.test(item, &Activation::from_captured(&captured), &cx)
-        });
+/// The `until` and `through` operators: `STREAM until P` produces the elements
+/// of `STREAM` up to, but not including, the first that matches `P`. `STREAM
+/// through P` includes the matching element as well.
+#[derive(Debug)]
+struct Until {
+    stream: Box<dyn Plan>,
+    capture_list: CaptureList,
+    predicate: Rc<dyn PredicatePlan>,
+    inclusive: bool,
+}
+
+impl Plan for Until {
+    fn run<'a, 'd>(&self, act: &'a Activation<'a, 'd>, cx: &Context<'d>) -> EvalResult<'d> {
+        let value = self.stream.run(act, cx)?;
+        let stream: Stream = value.try_unwrap()?;
+
+        // As for `Filter`, gather up owned versions of everything the predicate
+        // needs, so the stream becomes independent of this frame.
+        let captured = act.get_captured(&self.capture_list);
+        let predicate = self.predicate.clone();
+        let cx = cx.clone();
+        let iter = TakeUntil {
+            stream,
+            test: move |item: &Value<'d>| {
+                predicate.test(item, &Activation::from_captured(&captured), &cx)
+            },
+            inclusive: self.inclusive,
+            done: false,
+        };
         Ok(Value::from(Stream::new(iter)))
     }
 }
 
+/// An iterator producing the elements of `stream` until one passes `test`.
+/// If `inclusive` is true, produce the element that passed, too.
+#[derive(Clone)]
+struct TakeUntil<'d, F> {
+    stream: Stream<'d>,
+    test: F,
+    inclusive: bool,
+    done: bool,
+}
+
+impl<'d, F> FallibleIterator for TakeUntil<'d, F>
+where
+    F: FnMut(&Value<'d>) -> Result<bool, value::Error>,
+{
+    type Item = Value<'d>;
+    type Error = value::Error;
+
+    fn next(&mut self) -> Result<Option<Value<'d>>, value::Error> {
+        if self.done {
+            return Ok(None);
+        }
+        let item = match self.stream.next()? {
+            Some(item) => item,
+            None => {
+                self.done = true;
+                return Ok(None);
+            }
+        };
+        if (self.test)(&item)? {
+            self.done = true;
+            if !self.inclusive {
+                return Ok(None);
+            }
+        }
+        Ok(Some(item))
+    }
+}
+
 #[derive(Debug)]
 struct Paths(Box<dyn Plan>);
 impl Plan for Paths {
     fn run<'a, 'd>(&self, act: &'a Activation<'a, 'd>, cx: &Context<'d>) -> EvalResult<'d> {
         let value = self.0.run(act, cx)?;
-        let mut traversal = BreadthFirst::new(cx.dump);
-        match value {
-            Value::Node(node) => traversal.add_start_node(node.id),
-            Value::Stream(mut stream) => {
-                while let Some(elt) = stream.next()? {
-                    let node: &Node<'a> = elt.try_unwrap()?;
-                    traversal.add_start_node(node.id);
-                }
-            }
+        let starts: Vec<Node> = match value {
+            Value::Node(node) => vec![node],
+            Value::Stream(stream) => stream.map(|elt| elt.try_unwrap()).collect()?,
             other => {
                 return Err(value::Error::Type {
                     expected: "node or stream of nodes",
@@ -351,31 +902,72 @@ impl Plan for Paths {
             }
         };
 
+        // Traverse the snapshot the starting nodes belong to, which, in a
+        // comparison, may be the baseline.
+        let dump = starts.first().map_or(cx.dump, |node| node.dump());
+        let mut traversal = BreadthFirst::new(dump, cx.dangling_edges.clone());
+        for node in starts {
+            if !ptr::eq(node.dump(), dump) {
+                return Err(value::Error::MixedSnapshots);
+            }
+            traversal.add_start_node(node.id())?;
+        }
+
         // The traversal produces a stream of paths, where each path is
         // itself a stream of alternating nodes and edges.
-        let dump = cx.dump;
         let paths_iter = traversal
             .filter_map(move |path| {
                 if path.is_empty() {
-                    None
+                    Ok(None)
                 } else {
-                    // "Don't be too proud of this technological terror you've constructed."
-                    let start = dump.get_node(path[0].origin).unwrap();
-                    let iter = once(Value::from(start))
-                        .chain(path.into_iter().flat_map(move |Step { edge, .. }| {
-                            // If this edge is participating in a path, it
-                            // must have a referent...
-                            let referent = dump.get_node(edge.referent.unwrap()).unwrap();
-                            once(Value::from(edge)).chain(once(Value::from(referent)))
-                        })).map(Ok);
-                    Some(Stream::new(fallible_iterator::convert(iter)))
+                    let start = path[0].origin;
+                    Ok(Some(path_stream(dump, start, path)))
                 }
             })
-            .map(Value::from)
-            .map(Ok);
-        Ok(Value::from(Stream::new(fallible_iterator::convert(
-            paths_iter,
-        ))))
+            .map(|path| Ok(Value::from(path)));
+        Ok(Value::from(Stream::new(paths_iter)))
+    }
+}
+
+/// Return the path that starts at `start` and follows `steps` as a stream of
+/// alternating nodes and edges: `[ NODE EDGE NODE ... NODE ]`.
+fn path_stream<'d>(dump: &'d CoreDump<'d>, start: NodeId, steps: Vec<Step<'d>>) -> Stream<'d> {
+    // "Don't be too proud of this technological terror you've constructed."
+    let start = dump.get_node(start).unwrap();
+    let iter = once(Value::from(start))
+        .chain(steps.into_iter().flat_map(move |Step { edge, .. }| {
+            // If this edge is participating in a path, it must have a
+            // referent, and the traversal checked that it's in the dump.
+            let referent = dump.get_node(edge.referent.unwrap()).unwrap();
+            once(Value::from(edge)).chain(once(Value::from(referent)))
+        })).map(Ok);
+    Stream::new(fallible_iterator::convert(iter))
+}
+
+/// The `pathsto` built-in: `NODE pathsto` or `NODE (LIMIT pathsto)`.
+#[derive(Debug)]
+struct PathsTo {
+    target: Box<dyn Plan>,
+    limit: Option<Box<dyn Plan>>,
+}
+impl Plan for PathsTo {
+    fn run<'a, 'd>(&self, act: &'a Activation<'a, 'd>, cx: &Context<'d>) -> EvalResult<'d> {
+        let value = self.target.run(act, cx)?;
+        let target: Node = value.try_unwrap()?;
+        let limit = match &self.limit {
+            Some(plan) => {
+                let limit: u64 = plan.run(act, cx)?.try_unwrap()?;
+                limit as usize
+            }
+            None => 1,
+        };
+
+        let dump = target.dump();
+        let root = dump.get_root().id();
+        let paths_iter = retaining_paths(dump, target.id(), limit)
+            .into_iter()
+            .map(move |path| Ok(Value::from(path_stream(dump, root, path))));
+        Ok(Value::from(Stream::new(fallible_iterator::convert(paths_iter))))
     }
 }
 
@@ -416,6 +1008,211 @@ impl Plan for Map {
     }
 }
 
+#[derive(Debug)]
+struct GroupBy;
+
+// As with `Map`, `GroupBy` is both the plan and the primitive `Function`.
+impl<'dump> Callable<'dump> for GroupBy {
+    fn call_exact_arity(&self, actuals: &[Value<'dump>], cx: &Context<'dump>)
+                        -> EvalResult<'dump>
+    {
+        assert_eq!(actuals.len(), 2);
+        let stream: &Stream = actuals[0].try_unwrap_ref()?;
+        let mut groups = census(stream.clone(), &actuals[1], cx)?;
+
+        // Largest groups first.
+        groups.sort_by_key(|&(_, count, bytes)| Reverse((bytes, count)));
+
+        let iter = groups.into_iter().map(|(key, count, bytes)| {
+            Ok(Value::from(Rc::new(Record::new(vec![
+                ("key".to_owned(), key),
+                ("count".to_owned(), Value::from(count)),
+                ("bytes".to_owned(), Value::from(bytes)),
+            ]))))
+        });
+        Ok(Value::from(Stream::new(fallible_iterator::convert(iter))))
+    }
+
+    fn arity(&self) -> usize {
+        2
+    }
+
+    fn name(&self) -> Cow<str> {
+        Cow::Borrowed("groupby")
+    }
+}
+
+impl Plan for GroupBy {
+    fn run<'a, 'd>(&self, _act: &'a Activation<'a, 'd>, _cx: &Context<'d>) -> EvalResult<'d> {
+        Ok(Value::from(Function::new(GroupBy)))
+    }
+}
+
+/// Group the elements of `stream` by `key`, as for `apply_key`, and return each
+/// group's key value, count, and total size, in the order we first encountered
+/// them. Elements for which the key is absent belong to no group.
+fn census<'d>(mut stream: Stream<'d>, key: &Value<'d>, cx: &Context<'d>)
+              -> Result<Vec<(Value<'d>, u64, u64)>, value::Error>
+{
+    let mut groups: Vec<(Value<'d>, u64, u64)> = Vec::new();
+    let mut index: HashMap<Key, usize> = HashMap::new();
+    while let Some(value) = stream.next()? {
+        let key = match apply_key(key, &value, cx)? {
+            Some(key) => key,
+            None => continue,
+        };
+        let i = match index.entry(Key::from_value(&key)?) {
+            Entry::Occupied(entry) => *entry.get(),
+            Entry::Vacant(entry) => {
+                groups.push((key, 0, 0));
+                *entry.insert(groups.len() - 1)
+            }
+        };
+        let group = &mut groups[i];
+        group.1 += 1;
+        if let Value::Node(node) = value {
+            group.2 += node.size().unwrap_or(0);
+        }
+    }
+    Ok(groups)
+}
+
+/// Return the key of `value` for grouping or sorting, as directed by `key`:
+/// either a function to apply to `value`, or the name of a field of `value` to
+/// retrieve. Return `None` if `value` lacks the field.
+fn apply_key<'d>(key: &Value<'d>, value: &Value<'d>, cx: &Context<'d>)
+                 -> Result<Option<Value<'d>>, value::Error>
+{
+    match key {
+        Value::Function(fun) => fun.call(slice::from_ref(value), cx).map(Some),
+        Value::String(field) => get_field(value, field, cx),
+        other => Err(value::Error::Type {
+            expected: "function or field name",
+            actual: other.type_name(),
+        }),
+    }
+}
+
+#[derive(Debug)]
+struct SortBy;
+
+impl<'dump> Callable<'dump> for SortBy {
+    fn call_exact_arity(&self, actuals: &[Value<'dump>], cx: &Context<'dump>)
+                        -> EvalResult<'dump>
+    {
+        assert_eq!(actuals.len(), 2);
+        let stream: &Stream = actuals[0].try_unwrap_ref()?;
+        let mut stream = stream.clone();
+
+        let mut keyed = Vec::new();
+        while let Some(value) = stream.next()? {
+            // Elements for which the key is absent are dropped.
+            if let Some(key) = apply_key(&actuals[1], &value, cx)? {
+                keyed.push((Key::from_value(&key)?, value));
+            }
+        }
+
+        // This sort is stable, so elements with equal keys retain their order.
+        keyed.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+        let iter = keyed.into_iter().map(|(_, value)| Ok(value));
+        Ok(Value::from(Stream::new(fallible_iterator::convert(iter))))
+    }
+
+    fn arity(&self) -> usize {
+        2
+    }
+
+    fn name(&self) -> Cow<str> {
+        Cow::Borrowed("sortby")
+    }
+}
+
+impl Plan for SortBy {
+    fn run<'a, 'd>(&self, _act: &'a Activation<'a, 'd>, _cx: &Context<'d>) -> EvalResult<'d> {
+        Ok(Value::from(Function::new(SortBy)))
+    }
+}
+
+#[derive(Debug)]
+struct Top;
+
+impl<'dump> Callable<'dump> for Top {
+    fn call_exact_arity(&self, actuals: &[Value<'dump>], cx: &Context<'dump>)
+                        -> EvalResult<'dump>
+    {
+        assert_eq!(actuals.len(), 3);
+        let stream: &Stream = actuals[0].try_unwrap_ref()?;
+        let mut stream = stream.clone();
+        let limit: &u64 = actuals[2].try_unwrap_ref()?;
+        let limit = *limit as usize;
+
+        // Keep a heap of the `limit` largest elements seen so far, with the
+        // smallest on top, so we can discard it when something larger comes
+        // along. This way we never hold more than `limit + 1` elements.
+        let mut heap = BinaryHeap::new();
+        let mut seq = 0;
+        while let Some(value) = stream.next()? {
+            if let Some(key) = apply_key(&actuals[1], &value, cx)? {
+                heap.push(Reverse(Ranked { key: Key::from_value(&key)?, seq, value }));
+                if heap.len() > limit {
+                    heap.pop();
+                }
+            }
+            seq += 1;
+        }
+
+        // Sorting the `Reverse`d entries in ascending order puts the largest
+        // first.
+        let iter = heap.into_sorted_vec().into_iter().map(|Reverse(ranked)| Ok(ranked.value));
+        Ok(Value::from(Stream::new(fallible_iterator::convert(iter))))
+    }
+
+    fn arity(&self) -> usize {
+        3
+    }
+
+    fn name(&self) -> Cow<str> {
+        Cow::Borrowed("top")
+    }
+}
+
+impl Plan for Top {
+    fn run<'a, 'd>(&self, _act: &'a Activation<'a, 'd>, _cx: &Context<'d>) -> EvalResult<'d> {
+        Ok(Value::from(Function::new(Top)))
+    }
+}
+
+/// An element of a stream being ranked by `top`, along with its key and its
+/// position in the stream. Elements with larger keys rank higher; among
+/// elements with equal keys, earlier elements rank higher.
+#[derive(Clone)]
+struct Ranked<'a> {
+    key: Key,
+    seq: usize,
+    value: Value<'a>,
+}
+
+impl<'a> Ord for Ranked<'a> {
+    fn cmp(&self, other: &Ranked<'a>) -> Ordering {
+        self.key.cmp(&other.key).then(other.seq.cmp(&self.seq))
+    }
+}
+
+impl<'a> PartialOrd for Ranked<'a> {
+    fn partial_cmp(&self, other: &Ranked<'a>) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<'a> PartialEq for Ranked<'a> {
+    fn eq(&self, other: &Ranked<'a>) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<'a> Eq for Ranked<'a> {}
+
 #[derive(Debug)]
 struct EqualPredicate(Box<dyn Plan>);
 impl PredicatePlan for EqualPredicate {
@@ -425,6 +1222,48 @@ impl PredicatePlan for EqualPredicate {
     }
 }
 
+#[derive(Debug)]
+struct Compare {
+    op: CompareOp,
+    operand: Box<dyn Plan>,
+}
+impl PredicatePlan for Compare {
+    fn test<'a, 'd>(&self, value: &Value<'d>, act: &Activation<'a, 'd>, cx: &Context<'d>) -> Result<bool, value::Error> {
+        let value: &u64 = value.try_unwrap_ref()?;
+        let operand: u64 = self.operand.run(act, cx)?.try_unwrap()?;
+        Ok(match self.op {
+            CompareOp::Lt => *value < operand,
+            CompareOp::Le => *value <= operand,
+            CompareOp::Gt => *value > operand,
+            CompareOp::Ge => *value >= operand,
+        })
+    }
+}
+
+#[derive(Debug)]
+struct Range {
+    low: Option<Box<dyn Plan>>,
+    high: Option<Box<dyn Plan>>,
+}
+impl PredicatePlan for Range {
+    fn test<'a, 'd>(&self, value: &Value<'d>, act: &Activation<'a, 'd>, cx: &Context<'d>) -> Result<bool, value::Error> {
+        let value: &u64 = value.try_unwrap_ref()?;
+        if let Some(low) = &self.low {
+            let low: u64 = low.run(act, cx)?.try_unwrap()?;
+            if *value < low {
+                return Ok(false);
+            }
+        }
+        if let Some(high) = &self.high {
+            let high: u64 = high.run(act, cx)?.try_unwrap()?;
+            if *value >= high {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+}
+
 #[derive(Debug)]
 struct FieldPredicate {
     field_name: String,
@@ -432,53 +1271,13 @@ struct FieldPredicate {
 }
 impl PredicatePlan for FieldPredicate {
     fn test<'a, 'd>(&self, value: &Value<'d>, act: &Activation<'a, 'd>, cx: &Context<'d>) -> Result<bool, value::Error> {
-        let field = match value {
-            Value::Node(node) => get_node_field(node, &self.field_name)?,
-            Value::Edge(edge) => get_edge_field(edge, &self.field_name)?,
-            _ => {
-                return Err(value::Error::Type {
-                    expected: "node or edge",
-                    actual: value.type_name(),
-                });
-            }
-        };
+        let field = get_field(value, &self.field_name, cx)?;
         field.map_or(Ok(false), |field_value| {
             self.predicate.test(&field_value, act, cx)
         })
     }
 }
 
-fn get_node_field<'v>(node: &'v Node, field: &str) -> Result<Option<Value<'v>>, value::Error> {
-    Ok(match field {
-        "id" => Some(node.id.0.into()),
-        "size" => node.size.map(Value::from),
-        "coarseType" => Some(String::from(node.coarseType).into()),
-        "typeName" => node.typeName.map(|t| t.to_string().into()),
-        "JSObjectClassName" => node.JSObjectClassName.map(|t| t.to_string().into()),
-        "scriptFilename" => node.scriptFilename.map(|t| t.to_string().into()),
-        "descriptiveTypeName" => node.descriptiveTypeName.map(|t| t.to_string().into()),
-        _ => {
-            return Err(value::Error::NoSuchField {
-                value_type: "nodes",
-                field: field.into(),
-            })
-        }
-    })
-}
-
-fn get_edge_field<'v>(edge: &'v Edge, field: &str) -> Result<Option<Value<'v>>, value::Error> {
-    Ok(match field {
-        "referent" => edge.referent.map(|id| Value::from(id.0)),
-        "name" => edge.name.map(|n| n.to_string().into()),
-        _ => {
-            return Err(value::Error::NoSuchField {
-                value_type: "edges",
-                field: field.into(),
-            })
-        }
-    })
-}
-
 #[derive(Debug)]
 struct Ends(Box<dyn PredicatePlan>);
 impl PredicatePlan for Ends {
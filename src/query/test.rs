@@ -1,17 +1,25 @@
 #![cfg(test)]
 
+use crate::dump::CoreDump;
+use crate::dump::test_utils::{allocation_stack, delimited, frame, node, snapshot, uint};
+use std::path::Path;
 use super::test_utils::*;
-use super::QueryParser;
+use super::ast::{blank_comments, ArithOp, CompareOp, Expr, ExprKind, LambdaId, Predicate, PredicateKind, PredicateOp, Var};
+use super::lexer::Lexer;
+use super::{underline, Format, Library, ParseError, QueryParser, Span, StaticError};
+
+fn parse(text: &str) -> Result<Box<Expr>, ParseError> {
+    QueryParser::new().parse(Lexer::new(text))
+}
 
 #[test]
 fn parse_query() {
     assert_eq!(
-        QueryParser::new().parse("root").expect("parse failed"),
+        parse("root").expect("parse failed"),
         root()
     );
     assert_eq!(
-        QueryParser::new()
-            .parse("nodes { id: 0x0123456789abcdef }")
+        parse("nodes { id: 0x0123456789abcdef }")
             .expect("parse failed"),
         filter(
             nodes(),
@@ -19,3 +27,472 @@ fn parse_query() {
         )
     );
 }
+
+#[test]
+fn parse_nested_predicate() {
+    assert_eq!(
+        parse("nodes { allocationStack: any { line: 7 } }")
+            .expect("parse failed"),
+        filter(
+            nodes(),
+            and1(field("allocationStack", any(and1(field("line", expr_pred(number(7)))))))
+        )
+    );
+}
+
+#[test]
+fn allocation_stack_references() {
+    // Node 3's stack shares node 2's `outer` frame by reference, and node 4's
+    // stack is node 2's, entirely.
+    let bytes = snapshot(&[
+        node(1, 8, &[], &[]),
+        node(2, 8, &[], &allocation_stack(10, "inner", Some(&frame(11, "outer", None)))),
+        node(3, 8, &[], &allocation_stack(12, "other", Some(&uint(2, 11)))),
+        node(4, 8, &[], &delimited(6, &uint(2, 10))),
+    ]);
+    let dump = CoreDump::from_bytes(Path::new("stacks"), &bytes).unwrap();
+
+    let node_3 = "Node { id: 0x3, coarseType: Other, size: 8, allocationStack: 0xc }";
+    let node_4 = "Node { id: 0x4, coarseType: Other, size: 8, allocationStack: 0xa }";
+    assert_eq!(eval(&dump, None, "nodes { id: 3, allocationStack: any { functionDisplayName: \"outer\" } } first").unwrap(),
+               node_3);
+    assert_eq!(eval(&dump, None, "nodes { id: 3, allocationStack: any { id: 12, parent: { id: 11 } } } first").unwrap(),
+               node_3);
+    assert_eq!(eval(&dump, None, "nodes { id: 4, allocationStack: any { functionDisplayName: \"outer\" } } first").unwrap(),
+               node_4);
+    assert_eq!(eval(&dump, None, "nodes { id: 4, allocationStack: any { functionDisplayName: \"other\" } }").unwrap(),
+               "[\n]");
+}
+
+#[test]
+fn parse_until_through() {
+    assert_eq!(
+        parse("nodes until 7").expect("parse failed"),
+        pred_op(nodes(), PredicateOp::Until, expr_pred(number(7)))
+    );
+    assert_eq!(
+        parse("nodes through 7").expect("parse failed"),
+        pred_op(nodes(), PredicateOp::Through, expr_pred(number(7)))
+    );
+}
+
+#[test]
+fn parse_predicate_expr() {
+    assert_eq!(
+        parse("{ isSystem: true }").expect("parse failed"),
+        Box::new(Expr::from(ExprKind::Predicate {
+            id: LambdaId(0),
+            predicate: and1(field("isSystem", expr_pred(Box::new(Expr::from(ExprKind::Bool(true)))))),
+        }))
+    );
+}
+
+#[test]
+fn find_until_through() {
+    let bytes = snapshot(&[node(1, 8, &[], &[]), node(2, 16, &[], &[])]);
+    let dump = CoreDump::from_bytes(Path::new("find"), &bytes).unwrap();
+
+    assert_eq!(eval(&dump, None, "[1, 2, 3, 4] find 3").unwrap(), "3");
+    assert_eq!(eval(&dump, None, "[1, 2, 3, 4] until 3").unwrap(), "[\n1\n2\n]");
+    assert_eq!(eval(&dump, None, "[1, 2, 3, 4] through 3").unwrap(), "[\n1\n2\n3\n]");
+    assert_eq!(eval(&dump, None, "[1, 2, 3, 4] until 5").unwrap(), "[\n1\n2\n3\n4\n]");
+    assert_eq!(eval(&dump, None, "nodes find { size: 16 }").unwrap(), "Node { id: 0x2, coarseType: Other, size: 16 }");
+
+    let error = eval(&dump, None, "[1, 2, 3, 4] find 5").expect_err("find should fail");
+    assert_eq!(error.to_string(), "no stream element matched the predicate");
+
+    // None of them look past the first match: here, the element after it is an
+    // error.
+    let stream = "[1, 2, 3, [] first]";
+    assert_eq!(eval(&dump, None, &format!("{} find 3", stream)).unwrap(), "3");
+    assert_eq!(eval(&dump, None, &format!("{} until 3", stream)).unwrap(), "[\n1\n2\n]");
+    assert_eq!(eval(&dump, None, &format!("{} through 3", stream)).unwrap(), "[\n1\n2\n3\n]");
+    assert!(eval(&dump, None, &format!("{} until 7", stream)).is_err());
+}
+
+#[test]
+fn groupby_census() {
+    let typed = |id, size, coarse_type| node(id, size, &[], &uint(9, coarse_type));
+    // Other, Object, Script, String, DOMNode.
+    let bytes = snapshot(&[typed(1, 16, 0), typed(2, 24, 1), typed(3, 8, 2),
+                           typed(4, 24, 1), typed(5, 100, 3), typed(6, 8, 2),
+                           typed(7, 8, 4)]);
+    let dump = CoreDump::from_bytes(Path::new("groupby"), &bytes).unwrap();
+
+    // Largest `bytes` first; then largest `count`.
+    assert_eq!(eval(&dump, None, "nodes (\"coarseType\" groupby)").unwrap(),
+               "[\n\
+                { key: String, count: 1, bytes: 100 }\n\
+                { key: Object, count: 2, bytes: 48 }\n\
+                { key: Script, count: 2, bytes: 16 }\n\
+                { key: Other, count: 1, bytes: 16 }\n\
+                { key: DOMNode, count: 1, bytes: 8 }\n\
+                ]");
+    assert_eq!(eval(&dump, None, "nodes ((|n| n size) groupby) first").unwrap(),
+               "{ key: 100, count: 1, bytes: 100 }");
+}
+
+#[test]
+fn sortby_and_top() {
+    let bytes = snapshot(&[node(1, 32, &[], &[]), node(2, 8, &[], &[]),
+                           node(3, 16, &[], &[]), node(4, 24, &[], &[])]);
+    let dump = CoreDump::from_bytes(Path::new("sort"), &bytes).unwrap();
+    let eval = |query: &str| eval(&dump, None, query).unwrap();
+
+    assert_eq!(eval("nodes (\"size\" sortby) ((|n| n id) map)"), "[\n2\n3\n4\n1\n]");
+    assert_eq!(eval("[3, 1, 2] ((|x| x) sortby)"), "[\n1\n2\n3\n]");
+
+    // Keys of different types are ordered by type: booleans, numbers, strings,
+    // nodes.
+    assert_eq!(eval("[root, \"a\", 2, true, 1, false] ((|x| x) sortby)"),
+               "[\nfalse\ntrue\n1\n2\na\nNode { id: 0x1, coarseType: Other, size: 32 }\n]");
+
+    assert_eq!(eval("nodes (\"size\" (2 top)) ((|n| n id) map)"), "[\n1\n4\n]");
+    assert_eq!(eval("nodes (\"size\" (0 top))"), "[\n]");
+    assert_eq!(eval("nodes (\"size\" (10 top)) ((|n| n id) map)"), "[\n1\n4\n3\n2\n]");
+
+    // Both keep elements with equal keys in their original order, even as `top`
+    // discards elements from its heap.
+    let stream = "[3, 5, 1, 6, 2]";
+    assert_eq!(eval(&format!("{} ({{ 5 or 6 }} sortby)", stream)), "[\n3\n1\n2\n5\n6\n]");
+    assert_eq!(eval(&format!("{} ({{ 5 or 6 }} (3 top))", stream)), "[\n5\n6\n3\n]");
+    assert_eq!(eval(&format!("{} ({{ 5 or 6 }} (4 top))", stream)), "[\n5\n6\n3\n1\n]");
+}
+
+#[test]
+fn diff_operators() {
+    let typed = |id, size, coarse_type, edges| node(id, size, edges, &uint(9, coarse_type));
+    // Object is 1, String is 3.
+    let old = snapshot(&[typed(1, 8, 0, &[(2, "a"), (3, "b")]), typed(2, 16, 1, &[]), typed(3, 8, 3, &[])]);
+    let new = snapshot(&[typed(1, 8, 0, &[(3, "b"), (4, "c")]), typed(3, 16, 3, &[(5, "d")]),
+                         typed(4, 32, 1, &[]), typed(5, 16, 1, &[])]);
+    let old = CoreDump::from_bytes(Path::new("old"), &old).unwrap();
+    let new = CoreDump::from_bytes(Path::new("new"), &new).unwrap();
+
+    assert_eq!(eval(&new, Some(&old), "added (\"id\" sortby) id").unwrap(), "[\n4\n5\n]");
+    assert_eq!(eval(&new, Some(&old), "removed id").unwrap(), "[\n2\n]");
+    assert_eq!(eval(&new, Some(&old), "retained (\"id\" sortby) id").unwrap(), "[\n1\n3\n]");
+
+    // Retained nodes come from the later snapshot.
+    assert_eq!(eval(&new, Some(&old), "retained { id: 3 } first edges first referent").unwrap(), "5");
+
+    // Whatever grew the most comes first.
+    assert_eq!(eval(&new, Some(&old), "\"coarseType\" censusdiff").unwrap(),
+               "[\n\
+                { key: Object, oldCount: 1, newCount: 2, oldBytes: 16, newBytes: 48 }\n\
+                { key: String, oldCount: 1, newCount: 1, oldBytes: 8, newBytes: 16 }\n\
+                { key: Other, oldCount: 1, newCount: 1, oldBytes: 8, newBytes: 8 }\n\
+                ]");
+
+    // Without a baseline, there is nothing to compare against.
+    assert!(eval(&new, None, "added").is_err());
+    assert!(eval(&new, None, "\"coarseType\" censusdiff").is_err());
+}
+
+#[test]
+fn parse_definitions() {
+    // `let x = 7; let y = x; y` means `7 (|x| x (|y| y))`.
+    assert_eq!(
+        parse("let x = 7; let y = x; y").expect("parse failed"),
+        app(number(7), lambda(0, &["x"], app(var(0, "x"), lambda(0, &["y"], var(0, "y")))))
+    );
+    assert_eq!(
+        parse(&blank_comments("// Comments are blanked out.\nroot"))
+            .expect("parse failed"),
+        root()
+    );
+}
+
+#[test]
+fn parse_records() {
+    assert_eq!(
+        parse("{| id: 7, count: root .size |} .count").expect("parse failed"),
+        Box::new(Expr::from(ExprKind::Project {
+            arg: Box::new(Expr::from(ExprKind::Record(vec![
+                ("id".to_owned(), number(7)),
+                ("count".to_owned(), Box::new(Expr::from(ExprKind::Project { arg: root(), field: "size".to_owned() }))),
+            ]))),
+            field: "count".to_owned(),
+        }))
+    );
+    assert!(parse("{| id: 7, id: 8 |}").is_err());
+}
+
+#[test]
+fn parse_arithmetic() {
+    // Multiplication binds more tightly than addition; both associate left.
+    assert_eq!(
+        parse("1 + 2 * 3 - 4").expect("parse failed"),
+        Box::new(Expr::from(ExprKind::Arith {
+            op: ArithOp::Sub,
+            left: Box::new(Expr::from(ExprKind::Arith {
+                op: ArithOp::Add,
+                left: number(1),
+                right: Box::new(Expr::from(ExprKind::Arith { op: ArithOp::Mul, left: number(2), right: number(3) })),
+            })),
+            right: number(4),
+        }))
+    );
+    assert_eq!(
+        parse("8 / 2").expect("parse failed"),
+        Box::new(Expr::from(ExprKind::Arith { op: ArithOp::Div, left: number(8), right: number(2) }))
+    );
+}
+
+#[test]
+fn parse_division_and_regexes() {
+    let div = |left, right| Box::new(Expr::from(ExprKind::Arith { op: ArithOp::Div, left, right }));
+    let add = |left, right| Box::new(Expr::from(ExprKind::Arith { op: ArithOp::Add, left, right }));
+    let root_size = || app(root(), var(0, "size"));
+
+    // After an operand, `/` is division, with or without spaces.
+    assert_eq!(parse("(8/2) + (6/3)").expect("parse failed"),
+               add(div(number(8), number(2)), div(number(6), number(3))));
+    assert_eq!(parse("root size/2 + root size/2").expect("parse failed"),
+               add(div(root_size(), number(2)), div(root_size(), number(2))));
+    assert_eq!(parse("8/2/1").expect("parse failed"), div(div(number(8), number(2)), number(1)));
+
+    // Where an operand could begin, `/` starts a regex, even beside division.
+    let regex = regex::Regex::new("a/b").unwrap();
+    assert_eq!(
+        parse("(nodes { typeName: /a\\/b/ } count)/2").expect("parse failed"),
+        div(
+            app(
+                filter(nodes(), and1(field("typeName", Box::new(Predicate::from(PredicateKind::Regex(Box::new(regex))))))),
+                Box::new(Expr::from(ExprKind::Var(Var::Count))),
+            ),
+            number(2),
+        )
+    );
+    assert!(parse("nodes find /x/ count/2").is_ok());
+    assert!(parse("nodes { /unterminated }").is_err());
+}
+
+#[test]
+fn parse_comparisons() {
+    assert_eq!(
+        parse("nodes { size > 1000, size: 10..20 }").expect("parse failed"),
+        filter(
+            nodes(),
+            Box::new(Predicate::from(PredicateKind::And(vec![
+                *field("size", Box::new(Predicate::from(PredicateKind::Compare(CompareOp::Gt, number(1000))))),
+                *field("size", Box::new(Predicate::from(PredicateKind::Range(Some(number(10)), Some(number(20)))))),
+            ])))
+        )
+    );
+    assert_eq!(
+        parse("nodes { size: 64.. }").expect("parse failed"),
+        filter(
+            nodes(),
+            and1(field("size", Box::new(Predicate::from(PredicateKind::Range(Some(number(64)), None)))))
+        )
+    );
+    assert_eq!(
+        parse("nodes find <= 7").expect("parse failed"),
+        pred_op(nodes(), PredicateOp::Find, Box::new(Predicate::from(PredicateKind::Compare(CompareOp::Le, number(7)))))
+    );
+}
+
+#[test]
+fn arithmetic() {
+    let bytes = snapshot(&[node(1, 10, &[], &[])]);
+    let dump = CoreDump::from_bytes(Path::new("arithmetic"), &bytes).unwrap();
+    assert_eq!(eval(&dump, None, "root size + 3").unwrap(), "13");
+    assert_eq!(eval(&dump, None, "root size - 3").unwrap(), "7");
+    assert_eq!(eval(&dump, None, "root size * 3").unwrap(), "30");
+    assert_eq!(eval(&dump, None, "root size / 3").unwrap(), "3");
+    assert_eq!(eval(&dump, None, "2 + root size * 3 - 4 / 2").unwrap(), "30");
+
+    let error = eval(&dump, None, "root size / 0").expect_err("division by zero should fail");
+    assert_eq!(error.to_string(), "division by zero");
+    let error = eval(&dump, None, "1 - 2").expect_err("subtraction should underflow");
+    assert_eq!(error.to_string(), "arithmetic overflow");
+    let error = eval(&dump, None, "0x100000000 * 0x100000000").expect_err("multiplication should overflow");
+    assert_eq!(error.to_string(), "arithmetic overflow");
+    assert_eq!(eval(&dump, None, "0xffffffff * 0x100000001").unwrap(), "18446744073709551615");
+    assert_eq!(eval(&dump, None, "0xffffffffffffffff - 18446744073709551615").unwrap(), "0");
+}
+
+#[test]
+fn type_errors() {
+    let bytes = snapshot(&[node(1, 10, &[], &[])]);
+    let dump = CoreDump::from_bytes(Path::new("type_errors"), &bytes).unwrap();
+    let error = eval(&dump, None, "3 idom").expect_err("a number has no dominator");
+    assert_eq!(error.to_string(), "expected type node, got number");
+}
+
+#[test]
+fn comparisons_and_ranges() {
+    let bytes = snapshot(&[node(1, 15, &[], &[]), node(2, 16, &[], &[]),
+                           node(3, 63, &[], &[]), node(4, 64, &[], &[])]);
+    let dump = CoreDump::from_bytes(Path::new("ranges"), &bytes).unwrap();
+    let ids = |query: &str| eval(&dump, None, &format!("nodes {} (\"id\" sortby) id", query)).unwrap();
+
+    // Ranges include their lower bound and exclude their upper bound.
+    assert_eq!(ids("{ size: 16..64 }"), "[\n2\n3\n]");
+    assert_eq!(ids("{ size: 16.. }"), "[\n2\n3\n4\n]");
+    assert_eq!(ids("{ size: ..16 }"), "[\n1\n]");
+    assert_eq!(ids("{ size > 16 }"), "[\n3\n4\n]");
+    assert_eq!(ids("{ size >= 16 }"), "[\n2\n3\n4\n]");
+    assert_eq!(ids("{ size < 64 }"), "[\n1\n2\n3\n]");
+    assert_eq!(ids("{ size <= 63 }"), "[\n1\n2\n3\n]");
+}
+
+#[test]
+fn error_positions() {
+    fn error(text: &str) -> Option<&str> {
+        let error = Library::default().compile(text).expect_err("compile should fail");
+        error.span().map(|span| &text[span.start..span.end])
+    }
+    assert_eq!(error("nodes { size: bogus }"), Some("bogus"));
+    assert_eq!(error("let big = |s| s (bogus top);\nroot big"), Some("bogus"));
+    assert_eq!(error("root }"), Some("}"));
+    assert_eq!(error("root ("), Some(""));
+    assert_eq!(error("{| a: 1, a: 2 |}"), Some("2"));
+    assert_eq!(error("nodes { typeName: /a(/ }"), Some("/a(/"));
+    assert_eq!(error("nodes { typeName: #/a/#q }"), Some("#/a/#q"));
+    assert_eq!(error("root size + 99999999999999999999"), Some("99999999999999999999"));
+    assert_eq!(error("nodes { size: 0x10000000000000000 }"), Some("0x10000000000000000"));
+    assert!(matches!(Library::default().compile("0x10000000000000000"),
+                     Err(StaticError::Parse { ref message, .. }) if message == "number literal is too large"));
+    assert!(matches!(Library::default().compile("nodes { typeName: /[z-a]/ }"),
+                     Err(StaticError::Regex { .. })));
+
+    // Errors in definitions are caught when they are added to a library, and
+    // reported in the definitions' own text.
+    let mut library = Library::default();
+    let text = "let g = 1;\nlet f = |x| x bogus;";
+    let error = library.define(text).expect_err("definition should be rejected");
+    assert!(matches!(error, StaticError::UnboundVar { ref name, .. } if name == "bogus"));
+    let start = text.find("bogus").unwrap();
+    assert_eq!(error.span(), Some(Span { start, end: start + 5 }));
+
+    // A rejected text adds none of its definitions.
+    assert!(library.compile("g").is_err());
+    library.define("let f = |x| x;").expect("definition should be accepted");
+    assert!(library.compile("root f").is_ok());
+}
+
+#[test]
+fn underline_errors() {
+    let text = "let big = |s| s (10 top);\nnodes\t{ size: > \"x\" }";
+    let start = text.find('>').unwrap();
+    let span = Span { start, end: start + 7 };
+    assert_eq!(underline(text, span),
+               "    nodes\t{ size: > \"x\" }\n         \t        ^^^^^^^");
+    assert_eq!(underline(text, Span { start: text.len(), end: text.len() }),
+               "    nodes\t{ size: > \"x\" }\n         \t               ^");
+}
+
+#[test]
+fn parse_regex_flags() {
+    let regex = |text: &str| match parse(text).expect("parse failed").kind {
+        ExprKind::Predicate { predicate, .. } => match predicate.kind {
+            PredicateKind::And(predicates) => match &predicates[0].kind {
+                PredicateKind::Regex(regex) => (**regex).clone(),
+                other => panic!("not a regex: {:?}", other),
+            },
+            other => panic!("not a conjunction: {:?}", other),
+        },
+        other => panic!("not a predicate expression: {:?}", other),
+    };
+    assert!(regex("{ /window/i }").is_match("Window"));
+    assert!(!regex("{ /window/ }").is_match("Window"));
+    assert!(regex("{ #/a/b/#i }").is_match("A/B"));
+    assert_eq!(regex("{ /\\/x\\// }").as_str(), "/x/");
+}
+
+#[test]
+fn sum_overflow() {
+    let bytes = snapshot(&[node(1, 8, &[], &[])]);
+    let dump = CoreDump::from_bytes(Path::new("sum"), &bytes).unwrap();
+    assert_eq!(eval(&dump, None, "[0xfffffffffffffff0, 0xf] sum").unwrap(), "18446744073709551615");
+    let error = eval(&dump, None, "[0xfffffffffffffff0, 0x10] sum").expect_err("sum should overflow");
+    assert_eq!(error.to_string(), "arithmetic overflow");
+    let error = eval(&dump, None, "[0xfffffffffffffff0, 0x10] avg").expect_err("avg should overflow");
+    assert_eq!(error.to_string(), "arithmetic overflow");
+}
+
+#[test]
+fn removed_node_fields() {
+    // The baseline's node 2 was allocated in `inner`, called from `outer`. The
+    // current snapshot has a different frame with the same id.
+    let outer = frame(11, "outer", None);
+    let old = snapshot(&[node(1, 8, &[(2, "x")], &[]),
+                         node(2, 16, &[], &allocation_stack(10, "inner", Some(&outer)))]);
+    let new = snapshot(&[node(1, 8, &[(3, "y")], &[]),
+                         node(3, 32, &[], &allocation_stack(10, "elsewhere", None))]);
+    let old = CoreDump::from_bytes(Path::new("old"), &old).unwrap();
+    let new = CoreDump::from_bytes(Path::new("new"), &new).unwrap();
+
+    assert_eq!(eval(&new, Some(&old), "removed first allocationStack functionDisplayName").unwrap(),
+               "[\ninner\nouter\n]");
+    assert_eq!(eval(&new, Some(&old), "removed first allocationStack first parent functionDisplayName").unwrap(),
+               "outer");
+    assert_eq!(eval(&new, Some(&old), "removed first retainedSize").unwrap(), "16");
+    assert_eq!(eval(&new, Some(&old), "removed first referrers first first id").unwrap(), "1");
+    assert_eq!(eval(&new, Some(&old), "removed first idom id").unwrap(), "1");
+    assert_eq!(eval(&new, Some(&old), "removed first pathsto first count").unwrap(), "3");
+    assert!(eval(&new, Some(&old), "[root, removed first] paths").is_err());
+}
+
+#[test]
+fn operators_as_values() {
+    fn error(text: &str) -> Option<&str> {
+        match Library::with_prelude().compile(text) {
+            Err(error @ StaticError::Operator { .. }) => error.span().map(|span| &text[span.start..span.end]),
+            other => panic!("expected an operator error, got {:?}", other.err()),
+        }
+    }
+    assert_eq!(error("let total = sum; nodes size total"), Some("sum"));
+    assert_eq!(error("nodes (edges map)"), Some("edges"));
+    assert_eq!(error("root (3 pathsto) (paths map)"), Some("paths"));
+    assert!(Library::with_prelude().compile("nodes (|n| n edges count) max").is_ok());
+    assert!(Library::with_prelude().compile("root (3 pathsto)").is_ok());
+}
+
+#[test]
+fn table_columns() {
+    let bytes = snapshot(&[node(1, 8, &[(2, "child")], &[]), node(2, 16, &[], &[])]);
+    let dump = CoreDump::from_bytes(Path::new("table"), &bytes).unwrap();
+    let format: Format = "csv".parse().unwrap();
+
+    // Columns default to those of the first element.
+    assert_eq!(eval_formatted(&dump, None, "root edges", &format).unwrap(),
+               "name,referent\nchild,2\n");
+
+    // Elements that lack a column's field leave its cell empty.
+    let format = format.with_columns(vec!["id".to_owned(), "size".to_owned()]).unwrap();
+    assert_eq!(eval_formatted(&dump, None, "[root, root edges first]", &format).unwrap(),
+               "id,size\n1,8\n,\n");
+
+    // Misspelled columns are reported, rather than left empty, and so are
+    // fields of other kinds of values than the first element.
+    let format = format.with_columns(vec!["id".to_owned(), "szie".to_owned()]).unwrap();
+    let error = eval_formatted(&dump, None, "nodes", &format).expect_err("'szie' should be rejected");
+    assert_eq!(error.to_string(), "unknown column 'szie': the results have no field by that name");
+    let format = format.with_columns(vec!["id".to_owned(), "name".to_owned()]).unwrap();
+    assert!(eval_formatted(&dump, None, "nodes", &format).is_err());
+    assert!(eval_formatted(&dump, None, "[1, 2]", &format).is_err());
+    assert_eq!(eval_formatted(&dump, None, "[]", &format).unwrap(), "id,name\n");
+
+    // Records' own fields are columns too, taken from the first record.
+    let format = format.with_columns(vec!["a".to_owned(), "id".to_owned()]).unwrap();
+    assert_eq!(eval_formatted(&dump, None, "[{| a: 1, id: 7 |}, {| b: 2 |}, root]", &format).unwrap(),
+               "a,id\n1,7\n,\n,1\n");
+    assert!(eval_formatted(&dump, None, "[{| a: 1 |}, root]", &format).is_err());
+    assert!(eval_formatted(&dump, None, "[{| b: 2 |}, {| a: 1 |}]", &format).is_err());
+}
+
+#[test]
+fn implicit_fields() {
+    let library = Library::default();
+    assert!(library.compile("nodes size sum").is_ok());
+    assert!(library.compile("nodes (\"typeName\" groupby) bytes").is_ok());
+    assert!(matches!(library.compile("nodes sizee sum"),
+                     Err(StaticError::UnboundVar { ref name, .. }) if name == "sizee"));
+    assert!(matches!(library.compile("let f = |x| x bogus; root"),
+                     Err(StaticError::UnboundVar { ref name, .. }) if name == "bogus"));
+    assert!(library.compile("{| a: 1 |} .a").is_ok());
+    assert!(library.compile("{| a: 1 |} a").is_err());
+}
@@ -1,18 +1,20 @@
 #![cfg(test)]
 
-use super::ast::{Expr, LambdaId, Predicate, PredicateOp, UseId, Var};
+use crate::dump::CoreDump;
+use super::ast::{Expr, ExprKind, LambdaId, Predicate, PredicateKind, PredicateOp, UseId, Var};
+use super::{Activation, ActivationBase, Context, Format, Library};
 
 // Quick functions for building expressions.
 pub fn root() -> Box<Expr> {
-    Box::new(Expr::Var(Var::Root))
+    Box::new(Expr::from(ExprKind::Var(Var::Root)))
 }
 
 pub fn nodes() -> Box<Expr> {
-    Box::new(Expr::Var(Var::Nodes))
+    Box::new(Expr::from(ExprKind::Var(Var::Nodes)))
 }
 
 pub fn pred_op(stream: Box<Expr>, op: PredicateOp, predicate: Box<Predicate>) -> Box<Expr> {
-    Box::new(Expr::PredicateOp { id: LambdaId(0), stream, op, predicate })
+    Box::new(Expr::from(ExprKind::PredicateOp { id: LambdaId(0), stream, op, predicate }))
 }
 
 pub fn filter(stream: Box<Expr>, pred: Box<Predicate>) -> Box<Expr> {
@@ -20,39 +22,68 @@ pub fn filter(stream: Box<Expr>, pred: Box<Predicate>) -> Box<Expr> {
 }
 
 pub fn field(name: &str, pred: Box<Predicate>) -> Box<Predicate> {
-    Box::new(Predicate::Field(name.to_owned(), pred))
+    Box::new(Predicate::from(PredicateKind::Field(name.to_owned(), pred)))
 }
 
 pub fn number(n: u64) -> Box<Expr> {
-    Box::new(Expr::Number(n))
+    Box::new(Expr::from(ExprKind::Number(n)))
 }
 
 pub fn expr_pred(expr: Box<Expr>) -> Box<Predicate> {
-    Box::new(Predicate::Expr(*expr))
+    Box::new(Predicate::from(PredicateKind::Expr(*expr)))
 }
 
 pub fn and1(pred: Box<Predicate>) -> Box<Predicate> {
-    Box::new(Predicate::And(vec![*pred]))
+    Box::new(Predicate::from(PredicateKind::And(vec![*pred])))
+}
+
+pub fn any(pred: Box<Predicate>) -> Box<Predicate> {
+    Box::new(Predicate::from(PredicateKind::Any(pred)))
 }
 
 pub fn app(arg: Box<Expr>, fun: Box<Expr>) -> Box<Expr> {
-    Box::new(Expr::App { arg, fun })
+    Box::new(Expr::from(ExprKind::App { arg, fun }))
 }
 
 pub fn lambda<'a, F: 'a>(id: usize, formals: F, body: Box<Expr>) -> Box<Expr>
 where
     F: IntoIterator<Item = &'a &'static str>,
 {
-    Box::new(Expr::Lambda {
+    Box::new(Expr::from(ExprKind::Lambda {
         id: LambdaId(id),
         formals: formals.into_iter().map(|&f| f.to_owned()).collect(),
         body,
-    })
+    }))
 }
 
 pub fn var(id: usize, name: &str) -> Box<Expr> {
-    Box::new(Expr::Var(Var::Lexical {
+    Box::new(Expr::from(ExprKind::Var(Var::Lexical {
         id: UseId(id),
         name: name.to_owned(),
-    }))
+    })))
+}
+
+/// Run `query` against `dump`, comparing against `baseline` if given, and
+/// return its result as text, without the final newline.
+pub fn eval(dump: &CoreDump, baseline: Option<&CoreDump>, query: &str) -> Result<String, anyhow::Error> {
+    let mut text = eval_formatted(dump, baseline, query, &Format::Text)?;
+    text.pop();
+    Ok(text)
+}
+
+/// Run `query` against `dump`, comparing against `baseline` if given, and
+/// return its result written in `format`.
+pub fn eval_formatted(dump: &CoreDump, baseline: Option<&CoreDump>, query: &str, format: &Format)
+                      -> Result<String, anyhow::Error>
+{
+    let plan = Library::with_prelude().compile(query)?;
+    let cx = match baseline {
+        Some(baseline) => Context::with_baseline(dump, baseline),
+        None => Context::from_dump(dump),
+    };
+    let activation_base = ActivationBase::from_context(&cx);
+    let activation = Activation::for_eval(&activation_base);
+    let mut output = Vec::new();
+    plan.run(&activation, &cx)?.write_formatted(format, &cx, &mut output)?;
+    Ok(String::from_utf8(output).unwrap())
 }
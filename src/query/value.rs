@@ -1,11 +1,15 @@
-use crate::dump::{Edge, Node};
+use anyhow::bail;
+use crate::dump::{CoreDump, Edge, Frame, FrameId, Node, NodeId};
 use fallible_iterator::FallibleIterator;
 use std::cmp::PartialEq;
 use std::borrow::Cow;
 use std::io;
+use std::iter;
 use std::rc::Rc;
+use std::str::FromStr;
 use super::Context;
-use super::stream;
+use super::ast::Span;
+use super::{json, stream};
 
 /// A value produced by evaluating an expression.
 ///
@@ -18,8 +22,11 @@ use super::stream;
 pub enum Value<'a> {
     Number(u64),
     String(String),
-    Edge(&'a Edge<'a>),
-    Node(&'a Node<'a>),
+    Bool(bool),
+    Edge(Edge<'a>),
+    Node(Node<'a>),
+    Frame(&'a Frame<'a>),
+    Record(Rc<Record<'a>>),
     Stream(Stream<'a>),
     Function(Function<'a>),
 }
@@ -30,6 +37,60 @@ pub type Stream<'a> = stream::Stream<'a, Value<'a>, Error>;
 /// [`value::Error`](#type.Error).
 pub type EvalResult<'a> = Result<Value<'a>, Error>;
 
+/// A record: a sequence of named fields. Produced by record literals like
+/// `{| key: 1 |}`, and by built-ins like `groupby` and `censusdiff`.
+#[derive(Clone)]
+pub struct Record<'a> {
+    fields: Vec<(String, Value<'a>)>,
+}
+
+impl<'a> Record<'a> {
+    pub fn new(fields: Vec<(String, Value<'a>)>) -> Record<'a> {
+        Record { fields }
+    }
+
+    /// Return this record's fields, in order.
+    pub fn fields(&self) -> &[(String, Value<'a>)] {
+        &self.fields
+    }
+
+    /// Return the value of the field named `name`, if this record has one.
+    pub fn get(&self, name: &str) -> Option<&Value<'a>> {
+        self.fields.iter().find(|(n, _)| n == name).map(|(_, v)| v)
+    }
+}
+
+/// A stand-in for a `Value` that can be hashed and totally ordered, for grouping
+/// and sorting. Only some types of values have keys: booleans, numbers,
+/// strings, nodes (by id), and frames (by id). Keys of different types are
+/// ordered by type, in the order listed here.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Key {
+    Bool(bool),
+    Number(u64),
+    String(String),
+    Node(NodeId),
+    Frame(FrameId),
+}
+
+impl Key {
+    pub fn from_value(value: &Value) -> Result<Key, Error> {
+        Ok(match value {
+            Value::Bool(b) => Key::Bool(*b),
+            Value::Number(n) => Key::Number(*n),
+            Value::String(s) => Key::String(s.clone()),
+            Value::Node(node) => Key::Node(node.id()),
+            Value::Frame(frame) => Key::Frame(frame.id),
+            other => {
+                return Err(Error::Type {
+                    expected: "number, string, boolean, node, or frame",
+                    actual: other.type_name(),
+                })
+            }
+        })
+    }
+}
+
 #[derive(Clone)]
 pub struct Function<'a>(pub Rc<dyn 'a + Callable<'a>>);
 
@@ -57,7 +118,7 @@ pub trait Callable<'dump> {
 #[derive(Clone, thiserror::Error, Debug)]
 pub enum Error {
     /// Type mismatch.
-    #[error("expected type {actual}, got {expected}")]
+    #[error("expected type {expected}, got {actual}")]
     Type {
         actual: &'static str,
         expected: &'static str,
@@ -67,6 +128,10 @@ pub enum Error {
     #[error("stream produced no values")]
     EmptyStream,
 
+    /// `find` reached the end of its stream without a match.
+    #[error("no stream element matched the predicate")]
+    NoMatch,
+
     /// Matching on a non-existent Node or Edge field.
     #[error("{value_type} have no field named {field}")]
     NoSuchField {
@@ -74,9 +139,98 @@ pub enum Error {
         field: String,
     },
 
+    /// Projecting a field from a value that doesn't have it.
+    #[error("value has no {field} field")]
+    AbsentField {
+        field: String,
+    },
+
+    /// Using an operator like `added` without a baseline snapshot.
+    #[error("no baseline snapshot to compare against; use 'fxsnapshot diff OLD NEW QUERY'")]
+    NoBaseline,
+
+    /// Starting a traversal like `paths` from nodes in both the baseline and
+    /// the current snapshot.
+    #[error("can't traverse from nodes in different snapshots at once")]
+    MixedSnapshots,
+
+    /// Referring to an earlier query's result, like `%3`, when there is no
+    /// such result.
+    #[error("no earlier result named {name}")]
+    NoOutput {
+        name: String,
+    },
+
+    /// Asking for the immediate dominator of the root, or of a node not
+    /// reachable from the root.
+    #[error("node has no immediate dominator")]
+    NoImmediateDominator,
+
+    /// Starting a traversal from a node that isn't in the snapshot, like a
+    /// node from the baseline.
+    #[error("node {id:?} is not in the snapshot")]
+    NoSuchNode {
+        id: NodeId,
+    },
+
+    /// A traversal reached an edge whose referent isn't in the snapshot.
+    #[error("edge{} from node {origin:?} refers to node {referent:?}, which is not in the snapshot",
+            .name.as_ref().map_or(String::new(), |name| format!(" {:?}", name)))]
+    DanglingEdge {
+        origin: NodeId,
+        name: Option<String>,
+        referent: NodeId,
+    },
+
+    /// An arithmetic operation whose result is negative, or too large to
+    /// represent.
+    #[error("arithmetic overflow")]
+    Overflow,
+
+    /// Dividing by zero.
+    #[error("division by zero")]
+    DivideByZero,
+
     /// Attempt to apply a value that is not a function.
     #[error("attempt to apply value that is not a function")]
     NotAFunction,
+
+    /// Another error, attributed to a particular part of the query text.
+    #[error("{error}")]
+    At {
+        span: Span,
+        error: Box<Error>,
+    },
+}
+
+impl Error {
+    /// Attribute this error to `span` in the query text, unless it is already
+    /// attributed to some other part.
+    pub fn at(self, span: Span) -> Error {
+        match self {
+            Error::At { .. } => self,
+            error => Error::At { span, error: Box::new(error) },
+        }
+    }
+
+    /// Return the part of the query text to which this error is attributed, if
+    /// any.
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            Error::At { span, .. } => Some(*span),
+            _ => None,
+        }
+    }
+
+    /// Return true if this is a traversal reaching an edge whose referent
+    /// isn't in the snapshot, wherever it is attributed.
+    pub fn is_dangling_edge(&self) -> bool {
+        match self {
+            Error::At { error, .. } => error.is_dangling_edge(),
+            Error::DanglingEdge { .. } => true,
+            _ => false,
+        }
+    }
 }
 
 /// `Value` implements `TryUnwrap<T>` for each type `T` it can be unwrapped
@@ -104,7 +258,77 @@ enum Orientation {
     Vertical(usize),
 }
 
+/// How to write the result of a query.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub enum Format {
+    /// A layout for people to read. Streams are written one element per line.
+    #[default]
+    Text,
+
+    /// A single JSON value.
+    Json,
+
+    /// JSON Lines: each element of a stream as a JSON value on its own line.
+    JsonLines,
+
+    /// A table of values separated by `separator`, with a header row, and a
+    /// row for each element of a stream. Each column holds a field of the
+    /// elements, as listed in `columns`. If `columns` is empty, choose columns
+    /// suited to the first element.
+    Table {
+        separator: char,
+        columns: Vec<String>,
+    },
+}
+
+impl Format {
+    /// Return this format, but with the given table columns. It is an error to
+    /// specify columns for a format other than a table.
+    pub fn with_columns(self, columns: Vec<String>) -> Result<Format, anyhow::Error> {
+        match self {
+            Format::Table { separator, .. } => Ok(Format::Table { separator, columns }),
+            _ => bail!("columns can only be given for the 'csv' and 'tsv' formats"),
+        }
+    }
+}
+
+impl FromStr for Format {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Format, anyhow::Error> {
+        Ok(match s {
+            "text" => Format::Text,
+            "json" => Format::Json,
+            "jsonl" => Format::JsonLines,
+            "csv" => Format::Table { separator: ',', columns: vec![] },
+            "tsv" => Format::Table { separator: '\t', columns: vec![] },
+            _ => bail!("unrecognized output format '{}': expected 'text', 'json', 'jsonl', 'csv', or 'tsv'", s),
+        })
+    }
+}
+
 impl<'a> Value<'a> {
+    /// Write `self` to `stream` in the given format, followed by a newline.
+    /// Consult `cx` for fields of nodes and frames that tables need.
+    pub fn write_formatted(
+        &self,
+        format: &Format,
+        cx: &Context<'a>,
+        stream: &mut dyn io::Write,
+    ) -> Result<(), anyhow::Error> {
+        match format {
+            Format::Text => {
+                self.top_write(stream)?;
+                writeln!(stream)?;
+                Ok(())
+            }
+            Format::Json => json::write_json(self, stream),
+            Format::JsonLines => json::write_json_lines(self, stream),
+            Format::Table { separator, columns } => {
+                write_table(self, *separator, columns, cx, stream)
+            }
+        }
+    }
+
     pub fn top_write(&self, stream: &mut dyn io::Write) -> Result<(), anyhow::Error> {
         self.write(&Orientation::Vertical(0), stream)
     }
@@ -118,8 +342,13 @@ impl<'a> Value<'a> {
         match self {
             Value::Number(n) => write!(stream, "{}", n)?,
             Value::String(s) => write!(stream, "{}", s)?,
+            Value::Bool(b) => write!(stream, "{}", b)?,
             Value::Edge(e) => write!(stream, "{:?}", e)?,
             Value::Node(n) => write!(stream, "{:?}", n)?,
+            Value::Frame(f) => write!(stream, "{:?}", f)?,
+            Value::Record(r) => {
+                return write_record(r, orientation, stream);
+            }
             Value::Function(f) => write!(stream, "function {:?}", f.0.name())?,
             Value::Stream(s) => {
                 return write_stream(s, orientation, stream);
@@ -132,14 +361,111 @@ impl<'a> Value<'a> {
         match self {
             Value::Number(_) => "number",
             Value::String(_) => "string",
+            Value::Bool(_) => "boolean",
             Value::Edge(_) => "edge",
             Value::Node(_) => "node",
+            Value::Frame(_) => "frame",
+            Value::Record(_) => "record",
             Value::Stream(_) => "stream",
             Value::Function(_) => "function",
         }
     }
 }
 
+/// Return the value of `value`'s field named `field`, or `None` if `value` has
+/// no value for it. It is an error if `value` is not a structure, or if its
+/// type has no such field.
+pub fn get_field<'v>(value: &Value<'v>, field: &str, cx: &Context<'v>)
+                 -> Result<Option<Value<'v>>, Error>
+{
+    match value {
+        Value::Node(node) => get_node_field(node, field),
+        Value::Edge(edge) => get_edge_field(edge, field),
+        Value::Frame(frame) => get_frame_field(frame, field, frame_dump(frame, cx)),
+        Value::Record(record) => Ok(record.get(field).cloned()),
+        _ => {
+            Err(Error::Type {
+                expected: "node, edge, frame, or record",
+                actual: value.type_name(),
+            })
+        }
+    }
+}
+
+fn get_node_field<'v>(node: &Node<'v>, field: &str) -> Result<Option<Value<'v>>, Error> {
+    // In a comparison, `node` may come from either snapshot.
+    let dump = node.dump();
+    Ok(match field {
+        "id" => Some(node.id().0.into()),
+        "size" => node.size().map(Value::from),
+        "coarseType" => Some(String::from(node.coarseType()).into()),
+        "typeName" => node.typeName().map(|t| t.to_string().into()),
+        "JSObjectClassName" => node.JSObjectClassName().map(|t| t.to_string().into()),
+        "scriptFilename" => node.scriptFilename().map(|t| t.to_string().into()),
+        "descriptiveTypeName" => node.descriptiveTypeName().map(|t| t.to_string().into()),
+        "allocationStack" => node.allocationStack().map(|id| frame_stream(id, dump)),
+        "retainedSize" => dump.retained_size(node.id()).map(Value::from),
+        _ => {
+            return Err(Error::NoSuchField {
+                value_type: "nodes",
+                field: field.into(),
+            })
+        }
+    })
+}
+
+fn get_frame_field<'v>(frame: &'v Frame, field: &str, dump: &'v CoreDump<'v>)
+                       -> Result<Option<Value<'v>>, Error>
+{
+    Ok(match field {
+        "id" => Some(frame.id.0.into()),
+        "source" => frame.source.map(|s| s.to_string().into()),
+        "line" => frame.line.map(|n| Value::from(u64::from(n))),
+        "column" => frame.column.map(|n| Value::from(u64::from(n))),
+        "functionDisplayName" => frame.functionDisplayName.map(|n| n.to_string().into()),
+        "isSystem" => frame.isSystem.map(Value::from),
+        "isSelfHosted" => frame.isSelfHosted.map(Value::from),
+        "parent" => frame.parent.and_then(|id| dump.get_frame(id)).map(Value::from),
+        _ => {
+            return Err(Error::NoSuchField {
+                value_type: "frames",
+                field: field.into(),
+            })
+        }
+    })
+}
+
+/// Return the snapshot in `cx` that `frame` belongs to.
+fn frame_dump<'d>(frame: &Frame, cx: &Context<'d>) -> &'d CoreDump<'d> {
+    match cx.baseline {
+        Some(baseline) if baseline.owns_frame(frame) => baseline,
+        _ => cx.dump,
+    }
+}
+
+/// Return a stream of the frame whose id is `youngest` and its parents,
+/// youngest first.
+fn frame_stream<'v>(youngest: FrameId, dump: &'v CoreDump<'v>) -> Value<'v> {
+    let iter = iter::successors(dump.get_frame(youngest), move |frame| {
+        frame.parent.and_then(|id| dump.get_frame(id))
+    });
+    let iter = iter.map(|f| Ok(Value::from(f)));
+    Value::from(Stream::new(fallible_iterator::convert(iter)))
+}
+
+fn get_edge_field<'v>(edge: &Edge, field: &str) -> Result<Option<Value<'v>>, Error> {
+    Ok(match field {
+        "referent" => edge.referent.map(|id| Value::from(id.0)),
+        "name" => edge.name.map(|n| n.to_string().into()),
+        _ => {
+            return Err(Error::NoSuchField {
+                value_type: "edges",
+                field: field.into(),
+            })
+        }
+    })
+}
+
 fn write_stream<'a>(
     stream: &Stream<'a>,
     orientation: &Orientation,
@@ -179,14 +505,218 @@ fn write_stream<'a>(
     Ok(())
 }
 
+/// The names of the fields of nodes, edges, and frames, and of the records that
+/// built-ins produce.
+pub const BUILT_IN_FIELDS: &[&str] = &[
+    // nodes
+    "id", "size", "coarseType", "typeName", "JSObjectClassName", "scriptFilename",
+    "descriptiveTypeName", "allocationStack", "retainedSize",
+    // edges
+    "name", "referent",
+    // frames
+    "source", "line", "column", "functionDisplayName", "isSystem", "isSelfHosted", "parent",
+    // records from `groupby` and `censusdiff`
+    "key", "bytes", "oldCount", "newCount", "oldBytes", "newBytes",
+];
+
+/// The columns to use for a table of nodes, when none are specified.
+const NODE_COLUMNS: &[&str] = &[
+    "id", "coarseType", "typeName", "size",
+    "JSObjectClassName", "scriptFilename", "descriptiveTypeName",
+];
+
+/// The columns to use for a table of frames, when none are specified.
+const FRAME_COLUMNS: &[&str] = &["id", "functionDisplayName", "source", "line", "column"];
+
+/// The columns to use for a table of edges, when none are specified.
+const EDGE_COLUMNS: &[&str] = &["name", "referent"];
+
+/// Write `value` to `output` as a table, with fields separated by
+/// `separator`. Write a header row naming the columns, and then a row for each
+/// element of `value`, if it is a stream, or a single row for `value` itself,
+/// if it is not.
+///
+/// Each column holds the value of the field named in `columns`; absent fields
+/// are left empty. It is an error to name a column that the first element's
+/// kind has no field for: if it is a node, edge, or frame, a field of that type,
+/// and if it is a record, one of its own fields. If `columns` is empty,
+/// choose columns based on the first element. Rows for elements that are not
+/// nodes, edges, frames, or records have a single column, `value`, holding the
+/// element itself.
+fn write_table<'a>(
+    value: &Value<'a>,
+    separator: char,
+    columns: &[String],
+    cx: &Context<'a>,
+    output: &mut dyn io::Write,
+) -> Result<(), anyhow::Error> {
+    let mut stream = match value {
+        Value::Stream(stream) => stream.clone(),
+        _ => Stream::new(fallible_iterator::convert(iter::once(Ok(value.clone())))),
+    };
+
+    let first = stream.next()?;
+
+    // Check the columns against the first element before writing anything, so
+    // that a misspelled column isn't mistaken for a field that no element
+    // happens to have.
+    if let Some(first) = &first {
+        for column in columns {
+            let known = match first {
+                Value::Record(record) => record.get(column).is_some(),
+                _ => get_field(first, column, cx).is_ok(),
+            };
+            if !known {
+                bail!("unknown column '{}': the results have no field by that name", column);
+            }
+        }
+    }
+
+    let columns: Option<Vec<String>> = if !columns.is_empty() {
+        Some(columns.to_vec())
+    } else {
+        let owned = |names: &[&str]| names.iter().map(|&name| name.to_owned()).collect();
+        match &first {
+            Some(Value::Node(_)) => Some(owned(NODE_COLUMNS)),
+            Some(Value::Frame(_)) => Some(owned(FRAME_COLUMNS)),
+            Some(Value::Edge(_)) => Some(owned(EDGE_COLUMNS)),
+            Some(Value::Record(record)) => {
+                Some(record.fields.iter().map(|(name, _)| name.clone()).collect())
+            }
+            _ => None,
+        }
+    };
+
+    let header = match &columns {
+        Some(columns) => columns.clone(),
+        None => vec!["value".to_owned()],
+    };
+    write_table_row(header.iter().map(String::as_str), separator, output)?;
+
+    let mut next = first;
+    while let Some(element) = next {
+        let cells = match &columns {
+            Some(columns) => {
+                let mut cells = Vec::with_capacity(columns.len());
+                for column in columns {
+                    cells.push(match get_field(&element, column, cx) {
+                        Ok(Some(value)) => cell_text(&value)?,
+                        // In a stream of mixed nodes and edges, say, some
+                        // elements won't have every column's field, or a
+                        // record may lack a field the first one had.
+                        Ok(None) | Err(Error::NoSuchField { .. }) => String::new(),
+                        Err(error) => return Err(error.into()),
+                    });
+                }
+                cells
+            }
+            None => vec![cell_text(&element)?],
+        };
+        write_table_row(cells.iter().map(String::as_str), separator, output)?;
+        next = stream.next()?;
+    }
+    Ok(())
+}
+
+/// Write a row of a table, with cells separated by `separator`.
+///
+/// In comma-separated tables, cells containing commas, quotation marks, or line
+/// breaks are quoted, as RFC 4180 describes. In tab-separated tables, tabs, line
+/// breaks, and backslashes are written as backslash escapes.
+fn write_table_row<'c, I>(cells: I, separator: char, output: &mut dyn io::Write) -> io::Result<()>
+where
+    I: IntoIterator<Item = &'c str>,
+{
+    for (i, cell) in cells.into_iter().enumerate() {
+        if i > 0 {
+            write!(output, "{}", separator)?;
+        }
+        if separator == '\t' {
+            for ch in cell.chars() {
+                match ch {
+                    '\t' => write!(output, "\\t")?,
+                    '\n' => write!(output, "\\n")?,
+                    '\r' => write!(output, "\\r")?,
+                    '\\' => write!(output, "\\\\")?,
+                    ch => write!(output, "{}", ch)?,
+                }
+            }
+        } else if cell.contains([separator, '"', '\n', '\r']) {
+            write!(output, "\"{}\"", cell.replace('"', "\"\""))?;
+        } else {
+            write!(output, "{}", cell)?;
+        }
+    }
+    writeln!(output)
+}
+
+/// Return the text of a table cell holding `value`. Nodes and frames are
+/// identified by their ids, and edges by their names. The elements of a stream
+/// are separated by spaces.
+fn cell_text(value: &Value) -> Result<String, anyhow::Error> {
+    Ok(match value {
+        Value::Number(n) => n.to_string(),
+        Value::String(s) => s.clone(),
+        Value::Bool(b) => b.to_string(),
+        Value::Node(node) => format!("{:?}", node.id()),
+        Value::Frame(frame) => format!("{:?}", frame.id),
+        Value::Edge(edge) => edge.name.map(|name| name.to_string()).unwrap_or_default(),
+        Value::Record(_) | Value::Function(_) => {
+            let mut text = Vec::new();
+            value.write(&Orientation::Horizontal(0), &mut text)?;
+            String::from_utf8(text)?
+        }
+        Value::Stream(stream) => {
+            let mut stream = stream.clone();
+            let mut cells = Vec::new();
+            while let Some(element) = stream.next()? {
+                cells.push(cell_text(&element)?);
+            }
+            cells.join(" ")
+        }
+    })
+}
+
+/// Write `record` to `output` on a single line. Lay out any streams among its
+/// fields as `orientation` directs.
+fn write_record<'a>(
+    record: &Record<'a>,
+    orientation: &Orientation,
+    output: &mut dyn io::Write,
+) -> Result<(), anyhow::Error> {
+    let indent = match orientation {
+        Orientation::Horizontal(indent) | Orientation::Vertical(indent) => *indent,
+    };
+    let nested_orientation = Orientation::Horizontal(indent);
+    write!(output, "{{ ")?;
+    let mut first = true;
+    for (name, value) in &record.fields {
+        if !first {
+            write!(output, ", ")?;
+        }
+        write!(output, "{}: ", name)?;
+        value.write(&nested_orientation, output)?;
+        first = false;
+    }
+    write!(output, " }}")?;
+    Ok(())
+}
+
 impl<'a, 'b> PartialEq<Value<'a>> for Value<'b> {
     fn eq(&self, other: &Value<'a>) -> bool {
         use self::Value::*;
         match (self, other) {
             (Number(left), Number(right)) => left == right,
             (String(left), String(right)) => left == right,
+            (Bool(left), Bool(right)) => left == right,
             (Edge(left), Edge(right)) => left == right,
-            (Node(left), Node(right)) => left.id == right.id,
+            (Node(left), Node(right)) => left.id() == right.id(),
+            (Frame(left), Frame(right)) => left.id == right.id,
+            (Record(left), Record(right)) => {
+                left.fields.len() == right.fields.len() &&
+                    left.fields.iter().zip(&right.fields)
+                    .all(|((ln, lv), (rn, rv))| ln == rn && lv == rv)
+            }
             _ => false,
         }
     }
@@ -237,8 +767,11 @@ macro_rules! impl_value_variant {
 
 impl_value_variant!(u64, Number, "number");
 impl_value_variant!(String, String, "string");
-impl_value_variant!(&'a Edge<'a>, Edge, "edge");
-impl_value_variant!(&'a Node<'a>, Node, "node");
+impl_value_variant!(bool, Bool, "boolean");
+impl_value_variant!(Edge<'a>, Edge, "edge");
+impl_value_variant!(Node<'a>, Node, "node");
+impl_value_variant!(&'a Frame<'a>, Frame, "frame");
+impl_value_variant!(Rc<Record<'a>>, Record, "record");
 impl_value_variant!(Stream<'a>, Stream, "stream");
 impl_value_variant!(Function<'a>, Function, "function");
 
@@ -325,3 +858,23 @@ impl<'dump> Callable<'dump> for PartialApp<'dump> {
         Cow::Owned(format!("partial application of {}", self.function.0.name()))
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::write_table_row;
+
+    fn row(cells: &[&str], separator: char) -> String {
+        let mut output = Vec::new();
+        write_table_row(cells.iter().cloned(), separator, &mut output).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn table_rows() {
+        assert_eq!(row(&["0x10", "", "Window"], ','), "0x10,,Window\n");
+        assert_eq!(row(&["a,b", "say \"hi\"", "two\nlines"], ','),
+                   "\"a,b\",\"say \"\"hi\"\"\",\"two\nlines\"\n");
+        assert_eq!(row(&["a,b", "tab\there", "back\\slash"], '\t'),
+                   "a,b\ttab\\there\tback\\\\slash\n");
+    }
+}
@@ -1,6 +1,6 @@
 //! Traits for traversing `Expr` trees.
 
-use super::ast::{Expr, Predicate};
+use super::ast::{Expr, ExprKind, Predicate, PredicateKind};
 
 /// A type that can walk a query and accumulate information about it.
 ///
@@ -45,32 +45,52 @@ pub trait Walkable {
 }
 
 macro_rules! walk_expr_body {
-    ($expr:ident, $walker:ident) => {
+    ($expr:expr, $walker:ident) => {
         {
             match $expr {
-                Expr::StreamLiteral(elts) => {
+                ExprKind::StreamLiteral(elts) => {
                     for elt in elts {
                         $walker.walk_expr(elt)?;
                     }
                 }
 
-                Expr::PredicateOp { stream, predicate, .. } => {
+                ExprKind::PredicateOp { stream, predicate, .. } => {
                     $walker.walk_expr(stream)?;
                     $walker.walk_predicate(predicate)?;
                 }
 
-                Expr::App { arg, fun } => {
+                ExprKind::Predicate { predicate, .. } => {
+                    $walker.walk_predicate(predicate)?;
+                }
+
+                ExprKind::Record(fields) => {
+                    for (_name, value) in fields {
+                        $walker.walk_expr(value)?;
+                    }
+                }
+
+                ExprKind::Project { arg, .. } => {
+                    $walker.walk_expr(arg)?;
+                }
+
+                ExprKind::Arith { left, right, .. } => {
+                    $walker.walk_expr(left)?;
+                    $walker.walk_expr(right)?;
+                }
+
+                ExprKind::App { arg, fun } => {
                     $walker.walk_expr(arg)?;
                     $walker.walk_expr(fun)?;
                 }
 
-                Expr::Lambda { body, .. } => {
+                ExprKind::Lambda { body, .. } => {
                     $walker.walk_expr(body)?;
                 }
 
-                Expr::Number(_) => (),
-                Expr::String(_) => (),
-                Expr::Var(_) => (),
+                ExprKind::Number(_) => (),
+                ExprKind::String(_) => (),
+                ExprKind::Bool(_) => (),
+                ExprKind::Var(_) => (),
             };
             Ok(())
         }
@@ -79,29 +99,39 @@ macro_rules! walk_expr_body {
 
 impl Walkable for Expr {
     fn walk_children<'e, W: Walker<'e> + ?Sized>(&'e self, walker: &mut W) -> Result<(), W::Error> {
-        walk_expr_body!(self, walker)
+        walk_expr_body!(&self.kind, walker)
     }
 
     fn walk_children_mut<'e, W: WalkerMut<'e> + ?Sized>(&'e mut self, walker: &mut W) -> Result<(), W::Error> {
-        walk_expr_body!(self, walker)
+        walk_expr_body!(&mut self.kind, walker)
     }
 }
 
 macro_rules! walk_predicate_body {
-    ($predicate:ident, $walker:ident) => {
+    ($predicate:expr, $walker:ident) => {
         match $predicate {
-            Predicate::Expr(expr) => $walker.walk_expr(expr),
-            Predicate::Field(_name, subpred) => $walker.walk_predicate(subpred),
-            Predicate::Regex(_) => Ok(()),
-
-            Predicate::Ends(subpred) |
-            Predicate::Any(subpred) |
-            Predicate::All(subpred) |
-            Predicate::Not(subpred) =>
+            PredicateKind::Expr(expr) => $walker.walk_expr(expr),
+            PredicateKind::Field(_name, subpred) => $walker.walk_predicate(subpred),
+            PredicateKind::Regex(_) => Ok(()),
+            PredicateKind::Compare(_op, expr) => $walker.walk_expr(expr),
+            PredicateKind::Range(low, high) => {
+                if let Some(low) = low {
+                    $walker.walk_expr(low)?;
+                }
+                if let Some(high) = high {
+                    $walker.walk_expr(high)?;
+                }
+                Ok(())
+            }
+
+            PredicateKind::Ends(subpred) |
+            PredicateKind::Any(subpred) |
+            PredicateKind::All(subpred) |
+            PredicateKind::Not(subpred) =>
                 $walker.walk_predicate(subpred),
 
-            Predicate::And(subpreds) |
-            Predicate::Or(subpreds) => {
+            PredicateKind::And(subpreds) |
+            PredicateKind::Or(subpreds) => {
                 for subpred in subpreds {
                     $walker.walk_predicate(subpred)?;
                 }
@@ -113,10 +143,10 @@ macro_rules! walk_predicate_body {
 
 impl Walkable for Predicate {
     fn walk_children<'e, W: Walker<'e> + ?Sized>(&'e self, walker: &mut W) -> Result<(), W::Error> {
-        walk_predicate_body!(self, walker)
+        walk_predicate_body!(&self.kind, walker)
     }
 
     fn walk_children_mut<'e, W: WalkerMut<'e> + ?Sized>(&'e mut self, walker: &mut W) -> Result<(), W::Error> {
-        walk_predicate_body!(self, walker)
+        walk_predicate_body!(&mut self.kind, walker)
     }
 }
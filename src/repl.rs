@@ -0,0 +1,202 @@
+//! An interactive read-eval-print loop, for running many queries against a
+//! snapshot without reloading it each time.
+
+use anyhow::Error;
+use rustyline::DefaultEditor;
+use rustyline::error::ReadlineError;
+
+use crate::query::{self, Activation, ActivationBase, Context, Format, Lexer, Library, StaticError, Token, Value};
+use crate::report::{explain_query_error, report_skipped_edges};
+
+use std::io::Write;
+use std::path::PathBuf;
+
+/// Read queries from the terminal and print their results, until end of file.
+///
+/// Results are numbered, and later queries can refer to them: `%3` is the third
+/// query's result, and `%` alone is the most recent. Input consisting only of
+/// definitions adds them to `library`, for use by later queries. Results are
+/// written in `format`.
+pub fn run(cx: &Context, mut library: Library, format: &Format) -> Result<(), Error> {
+    let mut editor = DefaultEditor::new()?;
+    let history = history_path();
+    if let Some(path) = &history {
+        // There's no history file the first time around, and that's fine.
+        let _ = editor.load_history(path);
+    }
+
+    let mut outputs = Vec::new();
+    while let Some(text) = read_query(&mut editor, outputs.len() + 1)? {
+        if text.trim().is_empty() {
+            continue;
+        }
+        editor.add_history_entry(text.as_str())?;
+        match library.define(&text) {
+            Ok(()) => continue,
+            // Text that doesn't parse as definitions may still be a query.
+            Err(StaticError::Parse { .. }) => {}
+            Err(e) => {
+                eprintln!("{:#}", query::show_in_query(e.into(), &text));
+                continue;
+            }
+        }
+        let number = outputs.len() + 1;
+        match eval(&text, &library, &cx.with_outputs(&outputs), number, format) {
+            Ok(value) => outputs.push(value),
+            Err(e) => eprintln!("{:#}", explain_query_error(e, &text)),
+        }
+        report_skipped_edges(cx);
+    }
+
+    if let Some(path) = &history {
+        editor.save_history(path)?;
+    }
+    Ok(())
+}
+
+/// Read a query, continuing onto further lines as long as it has unclosed
+/// brackets or string literals. Return `None` at end of file.
+fn read_query(editor: &mut DefaultEditor, number: usize) -> Result<Option<String>, Error> {
+    let first_prompt = format!("In[{}]: ", number);
+    let rest_prompt = format!("{:>1$}: ", "...", first_prompt.len() - 2);
+
+    let mut text = String::new();
+    loop {
+        let prompt = if text.is_empty() { &first_prompt } else { &rest_prompt };
+        match editor.readline(prompt) {
+            Ok(line) => {
+                if !text.is_empty() {
+                    text.push('\n');
+                }
+                text.push_str(&line);
+            }
+            // Ctrl-C abandons the query being entered.
+            Err(ReadlineError::Interrupted) => return Ok(Some(String::new())),
+            Err(ReadlineError::Eof) if text.is_empty() => return Ok(None),
+            // Let the parser complain about whatever we have so far.
+            Err(ReadlineError::Eof) => return Ok(Some(text)),
+            Err(e) => return Err(e.into()),
+        }
+
+        if !is_incomplete(&text) {
+            return Ok(Some(text));
+        }
+    }
+}
+
+/// Return true if `text` has unclosed parentheses, brackets, or braces, or an
+/// unterminated string literal, suggesting that the query continues on the
+/// next line. This only needs to be good enough to decide when to stop
+/// reading; the parser has the final say on whether the query makes sense.
+fn is_incomplete(text: &str) -> bool {
+    let mut depth = 0;
+    for token in Lexer::new(text) {
+        match token {
+            Ok((_, Token::Fixed("(" | "[" | "{" | "{|"), _)) => depth += 1,
+            Ok((_, Token::Fixed(")" | "]" | "}" | "|}"), _)) => depth -= 1,
+            Ok(_) => (),
+            // The lexer stops at its first error. Only an unterminated string
+            // literal might be completed by the next line.
+            Err(error) => return error.span().is_some_and(|span| text[span.start..].starts_with('"')),
+        }
+    }
+    depth > 0
+}
+
+/// Compile and run the query `text` using the definitions in `library`, print
+/// its result in `format` as the `number`'th output, and return the result.
+fn eval<'a>(text: &str, library: &Library, cx: &Context<'a>, number: usize, format: &Format)
+            -> Result<Value<'a>, Error>
+{
+    let result = run_query(text, library, cx)?;
+
+    // Streams are lazy, so printing the result may fail partway through. If it
+    // does, finish the line, so the error message starts on a line of its own.
+    let stdout = std::io::stdout();
+    let mut stdout = stdout.lock();
+    write!(stdout, "Out[{}] = ", number)?;
+    if let Err(e) = result.write_formatted(format, cx, &mut stdout) {
+        writeln!(stdout)?;
+        return Err(e);
+    }
+
+    Ok(result)
+}
+
+/// Compile and run the query `text` using the definitions in `library`, in
+/// `cx`, whose outputs are the results of the queries so far.
+fn run_query<'a>(text: &str, library: &Library, cx: &Context<'a>) -> Result<Value<'a>, Error> {
+    let query = library.compile(text)?;
+    let activation_base = ActivationBase::from_context(cx);
+    let activation = Activation::for_eval(&activation_base);
+    Ok(query.run(&activation, cx)?)
+}
+
+/// Return the name of the file in which to save query history across
+/// sessions, if we can find the user's home directory.
+fn history_path() -> Option<PathBuf> {
+    let home = std::env::var_os("HOME")?;
+    Some(PathBuf::from(home).join(".fxsnapshot_history"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{is_incomplete, run_query};
+    use anyhow::Error;
+    use crate::dump::CoreDump;
+    use crate::dump::test_utils::{node, snapshot};
+    use crate::query::{Context, Library, Value};
+    use std::path::Path;
+
+    #[test]
+    fn earlier_outputs() {
+        let bytes = snapshot(&[node(1, 8, &[], &[])]);
+        let dump = CoreDump::from_bytes(Path::new("outputs"), &bytes).unwrap();
+        let cx = Context::from_dump(&dump);
+        let mut outputs = Vec::new();
+
+        // Run `text` as the REPL would, recording its result if it has one.
+        fn run<'a>(text: &str, cx: &Context<'a>, outputs: &mut Vec<Value<'a>>) -> Result<Value<'a>, Error> {
+            let value = run_query(text, &Library::default(), &cx.with_outputs(outputs))?;
+            outputs.push(value.clone());
+            Ok(value)
+        }
+
+        // `%` before any query has produced a result.
+        let error = run("%", &cx, &mut outputs).err().expect("% should fail");
+        assert_eq!(error.to_string(), "no earlier result named %");
+        assert!(outputs.is_empty());
+
+        assert!(matches!(run("root size", &cx, &mut outputs), Ok(Value::Number(8))));
+        assert!(matches!(run("root id", &cx, &mut outputs), Ok(Value::Number(1))));
+        assert!(matches!(run("%1", &cx, &mut outputs), Ok(Value::Number(8))));
+
+        // Failed queries don't get numbers.
+        let error = run("%4", &cx, &mut outputs).err().expect("%4 should fail");
+        assert_eq!(error.to_string(), "no earlier result named %4");
+        assert!(run("%0", &cx, &mut outputs).is_err());
+        assert!(matches!(run("%2", &cx, &mut outputs), Ok(Value::Number(1))));
+        assert!(matches!(run("%", &cx, &mut outputs), Ok(Value::Number(1))));
+        assert_eq!(outputs.len(), 5);
+    }
+
+    #[test]
+    fn incomplete_queries() {
+        assert!(!is_incomplete("root"));
+        assert!(!is_incomplete("nodes { id: 0x10 } first"));
+        assert!(is_incomplete("nodes {"));
+        assert!(is_incomplete("nodes { allocationStack: any { line: 7 }"));
+        assert!(is_incomplete("[root, (root"));
+        assert!(is_incomplete("nodes { typeName: \"JSObject"));
+        assert!(!is_incomplete("nodes { typeName: \"{\" }"));
+        assert!(!is_incomplete("nodes { typeName: /\\(/ }"));
+        assert!(!is_incomplete("nodes { typeName: #/(/# }"));
+        assert!(!is_incomplete("nodes }"));
+        assert!(!is_incomplete("(root size / 2)"));
+        assert!(is_incomplete("(root size / (2"));
+        assert!(!is_incomplete("(root size/2)"));
+        assert!(!is_incomplete("(8/2) + (6/3)"));
+        assert!(is_incomplete("[root size/2,"));
+        assert!(is_incomplete("[{| a: 1 |}, {| b: 2"));
+    }
+}
@@ -0,0 +1,26 @@
+//! Reporting query errors and warnings to the user, in terms of the command
+//! line and the query text.
+
+use anyhow::{anyhow, Error};
+
+use crate::query;
+
+/// Prepare `error`, from compiling or running the query `text`, for display:
+/// show where in `text` it arose, and if it's an edge to a node that isn't in
+/// the snapshot, say how to skip such edges.
+pub fn explain_query_error(error: Error, text: &str) -> Error {
+    let dangling = query::is_dangling_edge(&error);
+    let error = query::show_in_query(error, text);
+    if dangling {
+        return anyhow!("{:#}\nUse --lenient to skip edges to nodes not in the snapshot.", error);
+    }
+    error
+}
+
+/// If traversals run in `context` skipped any edges to absent nodes, say so.
+pub fn report_skipped_edges(context: &query::Context) {
+    let skipped = context.dangling_edges.take_skipped();
+    if skipped > 0 {
+        eprintln!("warning: skipped {} edges to nodes not in the snapshot", skipped);
+    }
+}